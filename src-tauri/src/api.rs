@@ -0,0 +1,492 @@
+use crate::commands::{Match, School, Stage, Work};
+use crate::error::AppError;
+use crate::http;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::RwLock;
+
+const SETTINGS_FILE: &str = "api_settings.json";
+
+// 平台接口地址，{domain} 替换为学校域名；可指向测试环境或本地 mock 服务
+static SETTINGS: once_cell::sync::Lazy<RwLock<ApiSettings>> =
+    once_cell::sync::Lazy::new(|| RwLock::new(ApiSettings::default()));
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct ApiSettings {
+    pub base_url: String,   // 学校接口，如 "https://{domain}.job3.posedu.cn"
+    pub public_url: String, // 不区分学校的公共接口（学校列表）
+    // 登录回调可用的本地端口，依次尝试，默认沿用平台登记的 3000；
+    // 都被占用或为空时使用系统分配的空闲端口
+    pub callback_ports: Vec<u16>,
+    pub login_timeout_secs: u64, // 等待浏览器授权的最长时间
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            base_url: "https://{domain}.job3.posedu.cn".to_string(),
+            public_url: "https://job3.posedu.cn".to_string(),
+            callback_ports: vec![3000],
+            login_timeout_secs: 300,
+        }
+    }
+}
+
+pub fn settings() -> ApiSettings {
+    SETTINGS.read().unwrap().clone()
+}
+
+pub fn configure(settings: ApiSettings) -> Result<(), String> {
+    for url in [&settings.base_url, &settings.public_url] {
+        let sample = url.replace("{domain}", "example");
+        reqwest::Url::parse(&sample).map_err(|e| format!("Invalid API URL {}: {}", url, e))?;
+    }
+    *SETTINGS.write().unwrap() = settings;
+    Ok(())
+}
+
+pub fn load_settings(config_dir: &Path) -> ApiSettings {
+    std::fs::read_to_string(config_dir.join(SETTINGS_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(config_dir: &Path, settings: &ApiSettings) -> Result<(), String> {
+    std::fs::create_dir_all(config_dir).map_err(|e| e.to_string())?;
+    let text = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(config_dir.join(SETTINGS_FILE), text)
+        .map_err(|e| format!("Failed to save API settings: {}", e))
+}
+
+// 平台统一的响应格式 {code, msg, data}
+#[derive(Deserialize)]
+struct Envelope {
+    code: i64,
+    msg: Option<String>,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+// 平台接口客户端，使用共享 HTTP 客户端和当前接口地址设置
+pub struct PosEduClient {
+    client: reqwest::Client,
+    base_url: String,
+    public_url: String,
+    access_token: Option<String>,
+}
+
+impl PosEduClient {
+    pub fn new(domain: &str) -> Self {
+        let settings = settings();
+        Self {
+            client: http::client(),
+            base_url: settings.base_url.replace("{domain}", domain),
+            public_url: settings.public_url,
+            access_token: None,
+        }
+    }
+
+    // 不区分学校的公共接口
+    pub fn public() -> Self {
+        Self::new("")
+    }
+
+    pub fn with_token(mut self, access_token: &str) -> Self {
+        self.access_token = Some(access_token.to_string());
+        self
+    }
+
+    pub fn authorize_url(&self) -> String {
+        format!("{}/school/oauth/authorize", self.base_url)
+    }
+
+    pub async fn schools(&self) -> Result<Vec<School>, AppError> {
+        let url = format!("{}/school/public_api/schools", self.public_url);
+        self.get_data(&url, &[]).await
+    }
+
+    // 授权码换取令牌，返回平台原始的令牌 JSON
+    pub async fn token(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        redirect_uri: &str,
+    ) -> Result<serde_json::Value, AppError> {
+        self.token_request(&[
+            ("code", code),
+            ("grant_type", "authorization_code"),
+            ("code_verifier", code_verifier),
+            ("redirect_uri", redirect_uri),
+        ])
+        .await
+    }
+
+    // 用 refresh_token 换取新令牌
+    pub async fn refresh_token(&self, refresh_token: &str) -> Result<serde_json::Value, AppError> {
+        self.token_request(&[
+            ("refresh_token", refresh_token),
+            ("grant_type", "refresh_token"),
+        ])
+        .await
+    }
+
+    async fn token_request(&self, params: &[(&str, &str)]) -> Result<serde_json::Value, AppError> {
+        let url = format!("{}/school/oauth/token", self.base_url);
+        let response = self.send(self.client.post(url).form(params)).await?;
+        decode_json(response).await
+    }
+
+    pub async fn userinfo(&self) -> Result<serde_json::Value, AppError> {
+        let url = format!("{}/school/oauth/userinfo", self.base_url);
+        let response = self.send(self.authorized(self.client.get(url))).await?;
+        decode_json(response).await
+    }
+
+    pub async fn matches(&self) -> Result<Vec<Match>, AppError> {
+        let url = format!("{}/school/match_api/matches", self.base_url);
+        self.get_data(&url, &[]).await
+    }
+
+    pub async fn stages(&self, match_id: i32) -> Result<Vec<Stage>, AppError> {
+        let url = format!("{}/school/match_api/stages", self.base_url);
+        self.get_data(&url, &[("match_id", match_id.to_string())])
+            .await
+    }
+
+    pub async fn works(&self, match_id: i32, stage_id: i32) -> Result<Vec<Work>, AppError> {
+        let url = format!("{}/school/match_api/works", self.base_url);
+        let query = [
+            ("match_id", match_id.to_string()),
+            ("stage_id", stage_id.to_string()),
+        ];
+        self.get_data(&url, &query).await
+    }
+
+    // 分页获取作品：支持 page/size 和游标两种分页，已知总数或未知时都会并发预取后续页；
+    // 服务器忽略分页参数、一次返回全部时自动退回单次请求的结果
+    pub async fn works_paged(
+        &self,
+        match_id: i32,
+        stage_id: i32,
+        paging: &PagingOptions,
+        on_progress: &(dyn Fn(WorksProgress) + Send + Sync),
+    ) -> Result<Vec<Work>, AppError> {
+        let size = paging.page_size.max(1);
+        let mut works = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+
+        let first = self
+            .works_page(match_id, stage_id, size, Page::Number(1))
+            .await?;
+        let total = first.total;
+        let mut cursor = first.next_cursor.clone();
+        let mut last_len = first.works.len();
+        let mut added = collect_new(&mut works, &mut seen, first.works);
+        let mut page = 1;
+        on_progress(WorksProgress {
+            page,
+            fetched: works.len(),
+            total,
+        });
+
+        // 游标分页只能逐页获取
+        while let Some(next) = cursor.take() {
+            page += 1;
+            let result = self
+                .works_page(match_id, stage_id, size, Page::Cursor(next))
+                .await?;
+            cursor = result.next_cursor;
+            if collect_new(&mut works, &mut seen, result.works) == 0 {
+                break;
+            }
+            on_progress(WorksProgress {
+                page,
+                fetched: works.len(),
+                total,
+            });
+        }
+        if page > 1 {
+            return Ok(works);
+        }
+
+        // page/size 分页：每次并发预取 concurrency 页，按页码顺序合并。
+        // 已知总数时取满总数为止，服务器可能把每页条数限制得比 size 小，按首页实际条数估算页数；
+        // 未知总数时不是整页说明已经到底。没有新作品（服务器忽略了分页参数）时都停止
+        let page_len = if total.is_some() {
+            last_len.max(1)
+        } else {
+            size as usize
+        };
+        let last_page = total.map(|total| total.div_ceil(page_len).max(1) as u32);
+        let finished = |fetched: usize, last_len: usize, added: usize| {
+            added == 0
+                || match total {
+                    Some(total) => fetched >= total,
+                    None => last_len != size as usize,
+                }
+        };
+        // 预取的页可能已经超出末尾：超出已知总数的页，或未知总数时被服务器拒绝的页，
+        // 其错误视为到底而不是整个列表失败；网络错误和 401 仍然报告
+        let past_end = |number: u32, error: &AppError| match total {
+            Some(total) => (number as usize - 1) * page_len >= total,
+            None => !error.retryable() && !matches!(error, AppError::Unauthorized(_)),
+        };
+        let mut done = finished(works.len(), last_len, added);
+        while !done && last_page.is_none_or(|last| page < last) {
+            let end = match last_page {
+                Some(last) => last.min(page + paging.concurrency.max(1) as u32),
+                None => page + paging.concurrency.max(1) as u32,
+            };
+            let requests = (page + 1..=end)
+                .map(|number| self.works_page(match_id, stage_id, size, Page::Number(number)));
+            let results = futures::future::join_all(requests).await;
+
+            for result in results {
+                let result = match result {
+                    Ok(result) => result,
+                    Err(e) if past_end(page + 1, &e) => {
+                        eprintln!("⚠️ Stopped listing works at page {}: {}", page + 1, e);
+                        done = true;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                };
+                page += 1;
+                last_len = result.works.len();
+                added = collect_new(&mut works, &mut seen, result.works);
+                on_progress(WorksProgress {
+                    page,
+                    fetched: works.len(),
+                    total,
+                });
+                if finished(works.len(), last_len, added) {
+                    done = true;
+                    break;
+                }
+            }
+        }
+
+        Ok(works)
+    }
+
+    async fn works_page(
+        &self,
+        match_id: i32,
+        stage_id: i32,
+        size: u32,
+        page: Page,
+    ) -> Result<WorksPage, AppError> {
+        let url = format!("{}/school/match_api/works", self.base_url);
+        let mut query = vec![
+            ("match_id", match_id.to_string()),
+            ("stage_id", stage_id.to_string()),
+            ("size", size.to_string()),
+        ];
+        match page {
+            Page::Number(number) => query.push(("page", number.to_string())),
+            Page::Cursor(cursor) => query.push(("cursor", cursor)),
+        }
+        let data: serde_json::Value = self.get_data(&url, &query).await?;
+        WorksPage::from_data(data)
+    }
+
+    // GET 并解析 {code, msg, data} 中的 data
+    async fn get_data<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, AppError> {
+        let request = self.authorized(self.client.get(url).query(query));
+        let response = self.send(request).await?;
+        let envelope: Envelope = decode_json(response).await?;
+        if envelope.code != 0 {
+            return Err(AppError::Platform {
+                code: envelope.code,
+                msg: envelope.msg.unwrap_or_else(|| "未知错误".to_string()),
+            });
+        }
+        Ok(serde_json::from_value(envelope.data)?)
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.access_token {
+            Some(ref token) => request.header("Authorization", format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, AppError> {
+        let response = http::send(request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            // 错误页可能很长，只保留开头用于提示
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::from_status(
+                status.as_u16(),
+                body.trim().chars().take(500).collect(),
+            ));
+        }
+        Ok(response)
+    }
+}
+
+async fn decode_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, AppError> {
+    let text = response.text().await?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct PagingOptions {
+    pub page_size: u32,
+    pub concurrency: usize, // 同时预取的页数
+}
+
+impl Default for PagingOptions {
+    fn default() -> Self {
+        Self {
+            page_size: 200,
+            concurrency: 4,
+        }
+    }
+}
+
+// 分页获取作品的进度
+#[derive(Clone, Serialize, Debug)]
+pub struct WorksProgress {
+    pub page: u32,
+    pub fetched: usize,
+    pub total: Option<usize>,
+}
+
+enum Page {
+    Number(u32),
+    Cursor(String),
+}
+
+struct WorksPage {
+    works: Vec<Work>,
+    total: Option<usize>,
+    next_cursor: Option<String>,
+}
+
+impl WorksPage {
+    // data 可能是作品数组（未分页），也可能是 {list|items|rows|records, total, next_cursor}
+    fn from_data(data: serde_json::Value) -> Result<Self, AppError> {
+        let decode = |value: serde_json::Value| {
+            serde_json::from_value::<Vec<Work>>(value).map_err(AppError::from)
+        };
+
+        match data {
+            serde_json::Value::Array(_) => Ok(Self {
+                works: decode(data)?,
+                total: None,
+                next_cursor: None,
+            }),
+            serde_json::Value::Object(mut object) => {
+                let list = ["list", "items", "rows", "records", "data"]
+                    .iter()
+                    .find_map(|key| object.remove(*key).filter(|v| v.is_array()))
+                    .ok_or_else(|| AppError::Decode("作品列表缺少 list 字段".to_string()))?;
+                let total = ["total", "count"]
+                    .iter()
+                    .find_map(|key| object.get(*key).and_then(|v| v.as_u64()))
+                    .map(|total| total as usize);
+                let next_cursor =
+                    ["next_cursor", "cursor"]
+                        .iter()
+                        .find_map(|key| match object.get(*key) {
+                            Some(serde_json::Value::String(s)) if !s.is_empty() => Some(s.clone()),
+                            Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+                            _ => None,
+                        });
+                Ok(Self {
+                    works: decode(list)?,
+                    total,
+                    next_cursor,
+                })
+            }
+            _ => Err(AppError::Decode("作品列表格式无法识别".to_string())),
+        }
+    }
+}
+
+// 按作品 ID 去重合并，返回新增数量
+fn collect_new(
+    works: &mut Vec<Work>,
+    seen: &mut std::collections::HashSet<i32>,
+    page: Vec<Work>,
+) -> usize {
+    let before = works.len();
+    works.extend(page.into_iter().filter(|work| seen.insert(work.id)));
+    works.len() - before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn work(id: i32) -> serde_json::Value {
+        json!({
+            "id": id,
+            "title": format!("作品{}", id),
+            "student_id": id,
+            "student_name": null,
+            "college_name": null,
+            "major_name": null,
+            "class_name": null,
+            "match_title": null,
+            "stage_name": null,
+            "check_status": 1,
+            "createtime": 0,
+            "files": []
+        })
+    }
+
+    #[test]
+    fn works_page_from_bare_array() {
+        let page = WorksPage::from_data(json!([work(1), work(2)])).unwrap();
+        assert_eq!(page.works.len(), 2);
+        assert_eq!(page.total, None);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn works_page_from_object_with_total() {
+        let data = json!({ "rows": [work(1), work(2)], "count": 5, "next_cursor": "" });
+        let page = WorksPage::from_data(data).unwrap();
+        assert_eq!(page.works.len(), 2);
+        assert_eq!(page.total, Some(5));
+        assert_eq!(page.next_cursor, None);
+
+        let page = WorksPage::from_data(json!({ "list": [], "cursor": 42 })).unwrap();
+        assert_eq!(page.next_cursor.as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn works_page_without_total() {
+        let page = WorksPage::from_data(json!({ "items": [work(1)] })).unwrap();
+        assert_eq!(page.works.len(), 1);
+        assert_eq!(page.total, None);
+
+        assert!(WorksPage::from_data(json!({ "total": 3 })).is_err());
+        assert!(WorksPage::from_data(json!("works")).is_err());
+    }
+
+    #[test]
+    fn short_last_page_adds_only_new_works() {
+        let mut works = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        let first = WorksPage::from_data(json!({ "list": [work(1), work(2)], "total": 3 }));
+        let last = WorksPage::from_data(json!({ "list": [work(2), work(3)], "total": 3 }));
+        assert_eq!(collect_new(&mut works, &mut seen, first.unwrap().works), 2);
+        let last = last.unwrap();
+        assert_eq!(last.works.len(), 2);
+        assert_eq!(collect_new(&mut works, &mut seen, last.works), 1);
+        assert_eq!(works.iter().map(|w| w.id).collect::<Vec<_>>(), [1, 2, 3]);
+    }
+}
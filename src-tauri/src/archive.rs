@@ -0,0 +1,413 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// 超过这些上限时需要 ZIP64 扩展字段
+const ZIP32_MAX: u64 = 0xFFFF_FFFF;
+const ZIP16_MAX: usize = 0xFFFF;
+// 预留 deflate 膨胀余量，接近 4GB 的文件直接按 ZIP64 写
+const ZIP64_ENTRY_THRESHOLD: u64 = 0xF000_0000;
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+// 压缩包内文件名编码
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NameEncoding {
+    #[default]
+    Utf8,
+    Gbk, // 兼容旧版 Windows 自带解压工具
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct ArchiveOptions {
+    pub name_encoding: NameEncoding,
+    pub per_student: bool, // 每个学生文件夹单独一个 zip，dest 视为目录
+    pub compress: bool,    // 默认仅存储，作品多为已压缩的视频/图片
+}
+
+pub struct ArchiveEntry {
+    pub source: PathBuf,
+    pub name: String, // 压缩包内路径，使用 '/' 分隔
+}
+
+#[derive(Clone, Serialize, Debug, Default)]
+pub struct ArchiveSummary {
+    pub archives: Vec<String>,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+// 将一组文件写入 dest，先写入临时文件，成功后再改名。返回 (写入字节数, 写入条目数)
+pub fn write_archive(
+    dest: &Path,
+    entries: &[ArchiveEntry],
+    options: &ArchiveOptions,
+    progress: &mut dyn FnMut(u64),
+) -> io::Result<(u64, usize)> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+
+    let result = (|| {
+        let file = File::create(&part)?;
+        let mut zip = ZipWriter::new(BufWriter::with_capacity(8 * 1024 * 1024, file));
+        let mut seen = HashSet::new();
+        let mut total = 0u64;
+
+        for entry in entries {
+            // 同名条目只保留第一个
+            if !seen.insert(entry.name.clone()) {
+                continue;
+            }
+            total += zip.add_file(entry, options, progress)?;
+        }
+
+        let written = seen.len();
+        zip.finish()?.flush()?;
+        Ok((total, written))
+    })();
+
+    match result {
+        Ok(written) => {
+            std::fs::rename(&part, dest)?;
+            Ok(written)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&part);
+            Err(e)
+        }
+    }
+}
+
+struct CentralRecord {
+    name: Vec<u8>,
+    flags: u16,
+    method: u16,
+    dos_time: u16,
+    dos_date: u16,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+    zip64: bool, // 本地文件头和数据描述符按 ZIP64 写入
+}
+
+// 流式 zip 写入器：条目数据后跟数据描述符，无需回写本地文件头
+struct ZipWriter<W: Write> {
+    out: CountingWriter<W>,
+    records: Vec<CentralRecord>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out: CountingWriter {
+                inner: out,
+                count: 0,
+            },
+            records: Vec::new(),
+        }
+    }
+
+    fn add_file(
+        &mut self,
+        entry: &ArchiveEntry,
+        options: &ArchiveOptions,
+        progress: &mut dyn FnMut(u64),
+    ) -> io::Result<u64> {
+        let mut source = File::open(&entry.source)?;
+        let metadata = source.metadata()?;
+        let (dos_time, dos_date) = dos_datetime(&metadata);
+
+        let (name, mut flags) = encode_name(&entry.name, options.name_encoding);
+        flags |= FLAG_DATA_DESCRIPTOR;
+        let method = if options.compress {
+            METHOD_DEFLATED
+        } else {
+            METHOD_STORED
+        };
+        let zip64 = metadata.len() >= ZIP64_ENTRY_THRESHOLD;
+        let offset = self.out.count;
+
+        // 本地文件头：CRC 和大小写在数据描述符里
+        let mut local_extra = Vec::new();
+        if zip64 {
+            local_extra.extend_from_slice(&0x0001u16.to_le_bytes());
+            local_extra.extend_from_slice(&16u16.to_le_bytes());
+            local_extra.extend_from_slice(&[0u8; 16]);
+        }
+        let size_placeholder = if zip64 { ZIP32_MAX as u32 } else { 0 };
+
+        let out = &mut self.out;
+        out.write_all(&0x0403_4b50u32.to_le_bytes())?;
+        out.write_all(&version_needed(zip64).to_le_bytes())?;
+        out.write_all(&flags.to_le_bytes())?;
+        out.write_all(&method.to_le_bytes())?;
+        out.write_all(&dos_time.to_le_bytes())?;
+        out.write_all(&dos_date.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&size_placeholder.to_le_bytes())?;
+        out.write_all(&size_placeholder.to_le_bytes())?;
+        out.write_all(&(name.len() as u16).to_le_bytes())?;
+        out.write_all(&(local_extra.len() as u16).to_le_bytes())?;
+        out.write_all(&name)?;
+        out.write_all(&local_extra)?;
+
+        // 文件数据
+        let data_start = out.count;
+        let mut hasher = crc32fast::Hasher::new();
+        let size = if options.compress {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(&mut *out, flate2::Compression::default());
+            let size = copy_with_crc(&mut source, &mut encoder, &mut hasher, progress)?;
+            encoder.finish()?;
+            size
+        } else {
+            copy_with_crc(&mut source, &mut *out, &mut hasher, progress)?
+        };
+        let compressed = out.count - data_start;
+        let crc = hasher.finalize();
+
+        if !zip64 && (size >= ZIP32_MAX || compressed >= ZIP32_MAX) {
+            return Err(io::Error::other(format!(
+                "{} grew past 4GB while archiving",
+                entry.name
+            )));
+        }
+
+        // 数据描述符
+        out.write_all(&0x0807_4b50u32.to_le_bytes())?;
+        out.write_all(&crc.to_le_bytes())?;
+        if zip64 {
+            out.write_all(&compressed.to_le_bytes())?;
+            out.write_all(&size.to_le_bytes())?;
+        } else {
+            out.write_all(&(compressed as u32).to_le_bytes())?;
+            out.write_all(&(size as u32).to_le_bytes())?;
+        }
+
+        self.records.push(CentralRecord {
+            name,
+            flags,
+            method,
+            dos_time,
+            dos_date,
+            crc,
+            compressed,
+            size,
+            offset,
+            zip64,
+        });
+
+        Ok(size)
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        let cd_start = self.out.count;
+
+        for record in &self.records {
+            // 本地按 ZIP64 写入的条目在中央目录也写入大小扩展，与本地记录一致；
+            // 偏移量溢出时追加。顺序固定
+            let mut zip64_extra = Vec::new();
+            if record.zip64 {
+                zip64_extra.extend_from_slice(&record.size.to_le_bytes());
+                zip64_extra.extend_from_slice(&record.compressed.to_le_bytes());
+            }
+            if record.offset >= ZIP32_MAX {
+                zip64_extra.extend_from_slice(&record.offset.to_le_bytes());
+            }
+            let zip64 = !zip64_extra.is_empty();
+
+            let mut extra = Vec::new();
+            if zip64 {
+                extra.extend_from_slice(&0x0001u16.to_le_bytes());
+                extra.extend_from_slice(&(zip64_extra.len() as u16).to_le_bytes());
+                extra.extend_from_slice(&zip64_extra);
+            }
+
+            let out = &mut self.out;
+            out.write_all(&0x0201_4b50u32.to_le_bytes())?;
+            out.write_all(&VERSION_ZIP64.to_le_bytes())?; // version made by
+            out.write_all(&version_needed(zip64).to_le_bytes())?;
+            out.write_all(&record.flags.to_le_bytes())?;
+            out.write_all(&record.method.to_le_bytes())?;
+            out.write_all(&record.dos_time.to_le_bytes())?;
+            out.write_all(&record.dos_date.to_le_bytes())?;
+            out.write_all(&record.crc.to_le_bytes())?;
+            if record.zip64 {
+                out.write_all(&(ZIP32_MAX as u32).to_le_bytes())?;
+                out.write_all(&(ZIP32_MAX as u32).to_le_bytes())?;
+            } else {
+                out.write_all(&(record.compressed as u32).to_le_bytes())?;
+                out.write_all(&(record.size as u32).to_le_bytes())?;
+            }
+            out.write_all(&(record.name.len() as u16).to_le_bytes())?;
+            out.write_all(&(extra.len() as u16).to_le_bytes())?;
+            out.write_all(&0u16.to_le_bytes())?; // comment
+            out.write_all(&0u16.to_le_bytes())?; // disk number
+            out.write_all(&0u16.to_le_bytes())?; // internal attributes
+            out.write_all(&0u32.to_le_bytes())?; // external attributes
+            out.write_all(&clamp32(record.offset).to_le_bytes())?;
+            out.write_all(&record.name)?;
+            out.write_all(&extra)?;
+        }
+
+        let cd_end = self.out.count;
+        let cd_size = cd_end - cd_start;
+        let count = self.records.len();
+        let out = &mut self.out;
+
+        if count >= ZIP16_MAX || cd_size >= ZIP32_MAX || cd_start >= ZIP32_MAX {
+            // ZIP64 end of central directory record + locator
+            out.write_all(&0x0606_4b50u32.to_le_bytes())?;
+            out.write_all(&44u64.to_le_bytes())?;
+            out.write_all(&VERSION_ZIP64.to_le_bytes())?;
+            out.write_all(&VERSION_ZIP64.to_le_bytes())?;
+            out.write_all(&0u32.to_le_bytes())?;
+            out.write_all(&0u32.to_le_bytes())?;
+            out.write_all(&(count as u64).to_le_bytes())?;
+            out.write_all(&(count as u64).to_le_bytes())?;
+            out.write_all(&cd_size.to_le_bytes())?;
+            out.write_all(&cd_start.to_le_bytes())?;
+
+            out.write_all(&0x0706_4b50u32.to_le_bytes())?;
+            out.write_all(&0u32.to_le_bytes())?;
+            out.write_all(&cd_end.to_le_bytes())?;
+            out.write_all(&1u32.to_le_bytes())?;
+        }
+
+        let count16 = count.min(ZIP16_MAX) as u16;
+        out.write_all(&0x0605_4b50u32.to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+        out.write_all(&count16.to_le_bytes())?;
+        out.write_all(&count16.to_le_bytes())?;
+        out.write_all(&clamp32(cd_size).to_le_bytes())?;
+        out.write_all(&clamp32(cd_start).to_le_bytes())?;
+        out.write_all(&0u16.to_le_bytes())?;
+
+        Ok(self.out.inner)
+    }
+}
+
+fn copy_with_crc(
+    source: &mut File,
+    sink: &mut dyn Write,
+    hasher: &mut crc32fast::Hasher,
+    progress: &mut dyn FnMut(u64),
+) -> io::Result<u64> {
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut size = 0u64;
+    loop {
+        let n = source.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(size);
+        }
+        hasher.update(&buffer[..n]);
+        sink.write_all(&buffer[..n])?;
+        size += n as u64;
+        progress(n as u64);
+    }
+}
+
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 返回 (文件名字节, 标志位)；GBK 模式下不设置 UTF-8 标志
+fn encode_name(name: &str, encoding: NameEncoding) -> (Vec<u8>, u16) {
+    if encoding == NameEncoding::Gbk && !name.is_ascii() {
+        let (bytes, _, had_errors) = encoding_rs::GBK.encode(name);
+        // 含 GBK 无法表示的字符时退回 UTF-8
+        if !had_errors {
+            return (bytes.into_owned(), 0);
+        }
+    }
+
+    let flags = if name.is_ascii() { 0 } else { FLAG_UTF8 };
+    (name.as_bytes().to_vec(), flags)
+}
+
+fn version_needed(zip64: bool) -> u16 {
+    if zip64 {
+        VERSION_ZIP64
+    } else {
+        VERSION_DEFAULT
+    }
+}
+
+fn clamp32(value: u64) -> u32 {
+    value.min(ZIP32_MAX) as u32
+}
+
+// 文件修改时间转换为 DOS 格式（本地时间，1980 年起）
+fn dos_datetime(metadata: &std::fs::Metadata) -> (u16, u16) {
+    use chrono::{Datelike, Timelike};
+
+    let modified = metadata
+        .modified()
+        .map(chrono::DateTime::<chrono::Local>::from)
+        .unwrap_or_else(|_| chrono::Local::now());
+
+    if modified.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+
+    let time = ((modified.hour() as u16) << 11)
+        | ((modified.minute() as u16) << 5)
+        | (modified.second() as u16 / 2);
+    let date = (((modified.year() - 1980) as u16) << 9)
+        | ((modified.month() as u16) << 5)
+        | modified.day() as u16;
+    (time, date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_names_are_not_counted() {
+        let dir = std::env::temp_dir().join(format!("archive-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.txt");
+        std::fs::write(&source, b"hello").unwrap();
+        let entries = ["a.txt", "a.txt", "b/a.txt"].map(|name| ArchiveEntry {
+            source: source.clone(),
+            name: name.to_string(),
+        });
+
+        let dest = dir.join("out.zip");
+        let written = write_archive(&dest, &entries, &ArchiveOptions::default(), &mut |_| {});
+        assert_eq!(written.unwrap(), (10, 2));
+        assert!(dest.is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,43 @@
-use crate::downloader::{DownloadItem, DownloadManager, DownloadProgress};
-use axum::{extract::Query, response::Html, Router};
-use serde::Deserialize;
+use crate::api::{self, ApiSettings, PagingOptions, PosEduClient, WorksProgress};
+use crate::archive::{self, ArchiveEntry, ArchiveOptions, ArchiveSummary};
+use crate::credentials;
+use crate::downloader::{
+    self, DownloadItem, DownloadManager, DownloadProgress, ItemMeta, RemoteInfo,
+};
+use crate::error::AppError;
+use crate::filter::{FileRules, WorkFilter};
+use crate::hooks::{self, HookConfig, HookOutput};
+use crate::http::{self, HttpSettings, HttpStats};
+use crate::layout::{self, LayoutPreset, PinyinStyle, Template};
+use crate::manifest::{self, ManifestRow};
+use crate::oauth::{CallbackServer, OauthFlow};
+use crate::paths;
+use crate::sanitize::{self, FsProfile, UnicodeForm};
+use crate::session::{self, TokenSet};
+use crate::sync::{self, SyncFile, SyncOptions, SyncReport, SyncStatus};
+use crate::xlsx::{self, LocalFiles, XlsxSummary};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tauri::{AppHandle, Emitter};
-use tokio::sync::mpsc;
+use tauri::{AppHandle, Emitter, Manager};
 use tokio::sync::Mutex;
+use tokio::sync::{mpsc, oneshot};
+
+// 进行中的授权（state、PKCE code_verifier 和回调地址）
+static OAUTH_FLOW: once_cell::sync::Lazy<Mutex<Option<OauthFlow>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+// 取消进行中的登录：回调服务关闭后 stopped 的发送端被丢弃，
+// 新的登录据此等旧服务释放端口后再绑定
+struct OauthCancel {
+    state: String,
+    cancel: oneshot::Sender<()>,
+    stopped: oneshot::Receiver<()>,
+}
 
-// OAuth callback state
-static OAUTH_STATE: once_cell::sync::Lazy<Arc<Mutex<Option<OauthResult>>>> =
-    once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(None)));
+static OAUTH_CANCEL: once_cell::sync::Lazy<Mutex<Option<OauthCancel>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
 
 #[derive(Clone, Debug, PartialEq)]
 enum BatchState {
@@ -24,6 +50,189 @@ struct BatchControlInfo {
     senders: Vec<mpsc::Sender<BatchControl>>,
     items: Vec<DownloadItem>, // 保存下载项以便恢复
     state: BatchState,        // 批次状态
+    save_path: String,
+    options: Arc<DownloadOptions>,
+    results: HashMap<String, ItemResult>, // 已结束（完成或失败）的下载项
+    batch_warnings: Vec<String>,
+    rejected: Vec<ExcludedFile>,
+    skipped: Vec<ExcludedFile>,
+    sync: Option<sync::SyncState>, // 增量同步批次结束时据此更新快照
+    finished: bool,
+}
+
+impl BatchControlInfo {
+    fn new(built: BuiltItems, save_path: String, options: Arc<DownloadOptions>) -> Self {
+        Self {
+            senders: Vec::new(),
+            items: built.items,
+            state: BatchState::Running,
+            save_path,
+            options,
+            results: HashMap::new(),
+            batch_warnings: Vec::new(),
+            rejected: built.rejected,
+            skipped: built.skipped,
+            sync: None,
+            finished: false,
+        }
+    }
+}
+
+// download_works 的可选参数
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct DownloadOptions {
+    pub hooks: Option<HookConfig>,
+    pub layout: Option<String>, // 目录模板，默认 layout::DEFAULT_LAYOUT
+    pub file_template: Option<String>, // 文件名模板，默认沿用原文件名
+    pub fs_profile: FsProfile,  // 文件名清理规则，默认按 Windows
+    pub unicode_form: UnicodeForm, // 路径名称的 Unicode 规范化形式，默认 NFC
+    pub pinyin: PinyinStyle,    // 学院/专业/班级/姓名转写为拼音，清单中仍保留中文
+    pub filter: Option<WorkFilter>, // 只处理符合条件的作品
+    pub file_rules: Option<FileRules>, // 只下载作品中符合规则的文件
+}
+
+impl DownloadOptions {
+    fn filter_works(&self, works: Vec<Work>) -> Vec<Work> {
+        match self.filter {
+            Some(ref filter) => filter.apply(works),
+            None => works,
+        }
+    }
+}
+
+// 单个下载任务的结束方式
+enum ItemOutcome {
+    Completed,
+    Interrupted, // 暂停或停止
+}
+
+#[derive(Clone, Debug)]
+struct ItemResult {
+    completed: bool,
+    error: Option<AppError>,
+    warnings: Vec<String>,
+    size: Option<u64>,
+    sha256: Option<String>,
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct ItemMessage {
+    pub id: Option<String>, // None 表示批次级别的警告
+    pub batch_id: Option<String>,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>, // 错误的 AppError 错误码，警告没有
+}
+
+// 未加入下载的文件（路径校验失败、被文件规则跳过或同步时无法暂存旧版本）
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ExcludedFile {
+    pub work_id: i32,
+    pub file_id: i32,
+    pub student_id: i32,
+    pub name: String,
+    pub reason: String,
+    #[serde(default)]
+    pub code: Option<String>, // 被拒绝时的 AppError 错误码，被规则跳过时没有
+    #[serde(default)]
+    pub meta: ItemMeta, // 写入清单用
+}
+
+impl ExcludedFile {
+    fn new(meta: ItemMeta, reason: String) -> Self {
+        Self {
+            work_id: meta.work_id,
+            file_id: meta.file_id,
+            student_id: meta.student_id,
+            name: meta.original_name.clone(),
+            reason,
+            code: None,
+            meta,
+        }
+    }
+
+    fn from_item(item: &DownloadItem, reason: String) -> Self {
+        Self::new(item.meta.clone().unwrap_or_default(), reason)
+    }
+
+    fn rejected(meta: ItemMeta, error: &AppError) -> Self {
+        Self {
+            code: Some(error.code().to_string()),
+            ..Self::new(meta, error.message())
+        }
+    }
+}
+
+// build_download_items 的结果
+struct BuiltItems {
+    items: Vec<DownloadItem>,
+    rejected: Vec<ExcludedFile>,      // 路径不安全，拒绝下载
+    skipped: Vec<ExcludedFile>,       // 被文件规则跳过
+    renamed: HashMap<String, String>, // 同目录重名而改名的下载项 id -> 原本的文件名
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct HookEvent {
+    pub id: Option<String>,
+    pub batch_id: Option<String>,
+    pub hook: String, // "post_file" | "post_batch"
+    pub output: HookOutput,
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct BatchReport {
+    pub batch_id: String,
+    pub save_path: String,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub pending: usize,
+    pub finished: bool,
+    pub errors: Vec<ItemMessage>,
+    pub warnings: Vec<ItemMessage>,
+    pub rejected: Vec<ExcludedFile>,
+    pub skipped: Vec<ExcludedFile>,
+}
+
+// plan_download 的结果，可原样交给 start_download_plan 开始下载
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DownloadPlan {
+    pub batch_id: Option<String>,
+    pub save_path: String,
+    pub options: DownloadOptions,
+    pub items: Vec<PlannedItem>,
+    pub total_size: u64,      // 已知大小之和
+    pub unknown_sizes: usize, // HEAD 未返回大小的文件数
+    pub conflicts: usize,     // 目标位置已有文件的数量
+    pub collisions: usize,    // 因重名改名的数量
+    pub rejected: Vec<ExcludedFile>,
+    pub skipped: Vec<ExcludedFile>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PlannedItem {
+    pub item: DownloadItem,
+    pub path: String,
+    pub size: Option<u64>,
+    pub existing: Option<ExistingFile>,
+    pub renamed_from: Option<String>, // 同目录重名时原本的文件名
+}
+
+// 目标位置已存在的文件；大小小于远程文件时下载会续传
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ExistingFile {
+    pub size: u64,
+    pub state: ExistingState,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingState {
+    Complete, // 与远程大小相同
+    Partial,  // 小于远程大小，将续传
+    Larger,   // 大于远程大小，内容可能不同
+    Unknown,  // 远程大小未知
 }
 
 type BatchTasksMap = Arc<Mutex<HashMap<String, BatchControlInfo>>>;
@@ -39,19 +248,6 @@ enum BatchControl {
 
 // 无效代码块已删除
 
-#[derive(Debug, Clone)]
-struct OauthResult {
-    code: Option<String>,
-    error: Option<String>,
-}
-
-#[derive(Deserialize)]
-struct CallbackParams {
-    code: Option<String>,
-    error: Option<String>,
-    state: Option<String>,
-}
-
 // Mock data structures
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct School {
@@ -61,274 +257,226 @@ pub struct School {
 }
 
 #[tauri::command]
-pub async fn get_schools() -> Result<Vec<School>, String> {
+pub async fn get_schools() -> Result<Vec<School>, AppError> {
     println!("Invoking get_schools...");
-    let client = reqwest::Client::builder()
-        .user_agent("MatchDownload/1.0")
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    let schools_url = "https://job3.posedu.cn/school/public_api/schools";
-    println!("Fetching schools from: {}", schools_url);
-
-    let response = client.get(schools_url).send().await.map_err(|e| {
-        println!("Request failed: {}", e);
-        e.to_string()
-    })?;
-
-    let status = response.status();
-    println!("Response status: {}", status);
-
-    if !status.is_success() {
-        let text = response.text().await.unwrap_or_default();
-        println!("Error response body: {}", text);
-        return Err(format!(
-            "获取学校列表失败: Status {}, Body: {}",
-            status, text
-        ));
-    }
-
-    let text = response.text().await.map_err(|e| e.to_string())?;
-    println!("Response body: {}", text);
-
-    let data: serde_json::Value =
-        serde_json::from_str(&text).map_err(|e| format!("JSON parsing failed: {}", e))?;
-
-    println!("Parsed JSON: {:?}", data);
-
-    if data["code"].as_i64() != Some(0) {
-        let msg = data["msg"].as_str().unwrap_or("未知错误");
-        println!("API returned error: {}", msg);
-        return Err(msg.to_string());
-    }
-
-    let schools: Vec<School> = serde_json::from_value(data["data"].clone())
-        .map_err(|e| format!("Data deserialization failed: {}", e))?;
-
+    let schools = PosEduClient::public().schools().await?;
     println!("Successfully parsed {} schools", schools.len());
     Ok(schools)
 }
 
 #[tauri::command]
-pub async fn start_oauth(app: AppHandle, domain: String) -> Result<String, String> {
+pub async fn start_oauth(app: AppHandle, domain: String) -> Result<String, AppError> {
     println!("=== start_oauth called with domain: {} ===", domain);
 
-    // OAuth configuration
-    let oauth_base_url = format!("https://{}.job3.posedu.cn/school/oauth/authorize", domain);
-    let redirect_uri = "http://localhost:3000/callback";
-    let state = uuid::Uuid::new_v4().to_string();
-
-    println!("OAuth URL: {}", oauth_base_url);
-    println!("Redirect URI: {}", redirect_uri);
-    println!("State: {}", state);
-
-    // Reset oauth state
-    println!("Resetting OAuth state...");
-    *OAUTH_STATE.lock().await = None;
-
-    // Start local HTTP server (non-blocking)
-    println!("Starting local callback server on port 3000...");
-    tokio::spawn(async move {
-        println!("Callback server task started");
-        if let Err(e) = run_callback_server().await {
-            eprintln!("Callback server error: {}", e);
-        }
+    // 先结束上一次登录，否则旧的回调服务仍占着登记的端口
+    cancel_pending_login().await;
+
+    // 每次登录单独启动回调服务，端口由系统分配或取自设置中登记的端口
+    let oauth_base_url = PosEduClient::new(&domain).authorize_url();
+    let mut flow = OauthFlow::new(&domain);
+    let server = CallbackServer::bind(&api::settings().callback_ports, &flow.state).await?;
+    flow.redirect_uri = server.redirect_uri();
+    let state = flow.state.clone();
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    let (stopped_tx, stopped_rx) = oneshot::channel::<()>();
+    *OAUTH_CANCEL.lock().await = Some(OauthCancel {
+        state: state.clone(),
+        cancel: cancel_tx,
+        stopped: stopped_rx,
     });
 
-    // Wait a bit for server to start
-    println!("Waiting for server to start...");
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-    println!("Server should be ready");
+    println!("OAuth URL: {}", oauth_base_url);
+    println!("Redirect URI: {}", flow.redirect_uri);
 
     // Build authorization URL
     let auth_url = format!(
-        "{}?redirect_uri={}&state={}",
+        "{}?redirect_uri={}&state={}&code_challenge={}&code_challenge_method=S256",
         oauth_base_url,
-        urlencoding::encode(redirect_uri),
-        state
+        urlencoding::encode(&flow.redirect_uri),
+        flow.state,
+        flow.code_challenge()
     );
+    *OAUTH_FLOW.lock().await = Some(flow);
 
-    println!("Opening browser with URL: {}", auth_url);
+    println!("Opening browser for authorization...");
 
     // Use opener plugin instead of shell
     use tauri_plugin_opener::OpenerExt;
-    app.opener()
-        .open_url(&auth_url, None::<&str>)
-        .map_err(|e| {
-            let err_msg = format!("Failed to open browser: {}", e);
-            eprintln!("{}", err_msg);
-            err_msg
-        })?;
+    if let Err(e) = app.opener().open_url(&auth_url, None::<&str>) {
+        let err_msg = format!("Failed to open browser: {}", e);
+        eprintln!("{}", err_msg);
+        // 丢弃 server 即关闭回调服务，同时清掉本次登录
+        drop(server);
+        clear_login(&state).await;
+        return Err(AppError::Internal(err_msg));
+    }
 
     println!("Browser opened, waiting for callback...");
 
-    // Wait for callback (max 5 minutes)
-    for i in 0..300 {
-        if i % 10 == 0 {
-            println!("Waiting for OAuth callback... {}s", i);
-        }
-
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-        let result = OAUTH_STATE.lock().await.clone();
-        if let Some(oauth_result) = result {
-            if let Some(code) = oauth_result.code {
-                println!("=== OAuth code received: {} ===", code);
-                return Ok(code);
-            } else if let Some(error) = oauth_result.error {
-                let err_msg = format!("OAuth error: {}", error);
-                eprintln!("{}", err_msg);
-                return Err(err_msg);
-            }
+    // 等待回调、超时或取消，结束后回调服务随即关闭
+    let timeout = Duration::from_secs(api::settings().login_timeout_secs.max(1));
+    let result = server.wait(timeout, cancel_rx).await;
+    drop(stopped_tx);
+    {
+        let mut cancel = OAUTH_CANCEL.lock().await;
+        if cancel.as_ref().is_some_and(|c| c.state == state) {
+            *cancel = None;
         }
     }
-
-    eprintln!("OAuth timeout after 300 seconds");
-    Err("OAuth timeout".to_string())
+    let code = result.inspect_err(|e| {
+        eprintln!("{}", e);
+    })?;
+    println!("=== OAuth code received ===");
+    Ok(code)
 }
 
-async fn run_callback_server() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Creating HTTP router...");
-    let app = Router::new().route("/callback", axum::routing::get(handle_callback));
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("Attempting to bind to {}", addr);
-
-    let listener = match tokio::net::TcpListener::bind(addr).await {
-        Ok(l) => {
-            println!("Successfully bound to port 3000");
-            l
-        }
-        Err(e) => {
-            eprintln!("Failed to bind to port 3000: {}", e);
-            return Err(Box::new(e));
-        }
-    };
-
-    println!("Starting axum server...");
-    axum::serve(listener, app).await?;
-    println!("Axum server stopped");
+// 取消进行中的登录，start_oauth 立即返回 cancelled 错误并关闭回调服务
+#[tauri::command]
+pub async fn cancel_oauth() -> Result<(), AppError> {
+    cancel_pending_login().await;
+    *OAUTH_FLOW.lock().await = None;
     Ok(())
 }
 
-async fn handle_callback(Query(params): Query<CallbackParams>) -> Html<String> {
-    println!("=== Callback received! ===");
-    println!("Code: {:?}", params.code);
-    println!("Error: {:?}", params.error);
-    println!("State: {:?}", params.state);
-
-    // Store result
-    *OAUTH_STATE.lock().await = Some(OauthResult {
-        code: params.code.clone(),
-        error: params.error.clone(),
-    });
-
-    println!("OAuth state updated");
-
-    // Return success page
-    let html = if params.code.is_some() {
-        r#"
-        <!DOCTYPE html>
-        <html>
-        <head>
-            <title>授权成功</title>
-            <style>
-                body { font-family: -apple-system, sans-serif; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); }
-                .card { background: white; padding: 40px; border-radius: 12px; text-align: center; box-shadow: 0 20px 60px rgba(0,0,0,0.3); }
-                h1 { color: #48bb78; margin: 0 0 16px 0; }
-                p { color: #4a5568; }
-            </style>
-            <script>
-                // 自动关闭标签页
-                setTimeout(function() {
-                    window.close();
-                }, 1500);
-            </script>
-        </head>
-        <body>
-            <div class="card">
-                <h1>✓ 授权成功</h1>
-                <p>页面将自动关闭...</p>
-            </div>
-        </body>
-        </html>
-        "#
-    } else {
-        r#"
-        <!DOCTYPE html>
-        <html>
-        <head>
-            <title>授权失败</title>
-            <style>
-                body { font-family: -apple-system, sans-serif; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); }
-                .card { background: white; padding: 40px; border-radius: 12px; text-align: center; box-shadow: 0 20px 60px rgba(0,0,0,0.3); }
-                h1 { color: #f56565; margin: 0 0 16px 0; }
-                p { color: #4a5568; }
-            </style>
-        </head>
-        <body>
-            <div class="card">
-                <h1>✗ 授权失败</h1>
-                <p>您可以关闭此页面</p>
-            </div>
-        </body>
-        </html>
-        "#
+// 发出取消信号并等待回调服务关闭；服务关闭最多等 2 秒，这里多留一点余量
+async fn cancel_pending_login() {
+    let Some(pending) = OAUTH_CANCEL.lock().await.take() else {
+        return;
     };
-
-    Html(html.to_string())
+    println!("🚫 Cancelling OAuth login");
+    let _ = pending.cancel.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(3), pending.stopped).await;
 }
 
-#[tauri::command]
-pub async fn exchange_token(code: String, domain: String) -> Result<serde_json::Value, String> {
-    let client = reqwest::Client::new();
-    let token_url = format!("https://{}.job3.posedu.cn/school/oauth/token", domain);
-
-    let params = [
-        ("code", code.as_str()),
-        ("grant_type", "authorization_code"),
-    ];
-
-    let response = client
-        .post(token_url)
-        .form(&params)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Token exchange failed: {}", error_text));
+// 清掉 state 对应的登录，不影响之后开始的新登录
+async fn clear_login(state: &str) {
+    {
+        let mut cancel = OAUTH_CANCEL.lock().await;
+        if cancel.as_ref().is_some_and(|c| c.state == state) {
+            *cancel = None;
+        }
     }
+    let mut flow = OAUTH_FLOW.lock().await;
+    if flow.as_ref().is_some_and(|f| f.state == state) {
+        *flow = None;
+    }
+}
 
-    let token_data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+// 返回给前端的令牌信息，refresh_token 只保留在后端
+#[derive(Clone, Serialize, Debug)]
+pub struct AccessToken {
+    pub access_token: String,
+    pub expires_at: Option<i64>,
+}
 
-    Ok(token_data)
+// 换取令牌并由后端保存，之后的接口调用会自动刷新；
+// 授权码只能配合本次 start_oauth 生成的 code_verifier 使用
+#[tauri::command]
+pub async fn exchange_token(code: String, domain: String) -> Result<AccessToken, AppError> {
+    // 学校不匹配时保留进行中的登录，只有匹配时才取出
+    let flow = {
+        let mut pending = OAUTH_FLOW.lock().await;
+        match pending.as_ref() {
+            Some(flow) if flow.domain == domain => pending.take(),
+            _ => None,
+        }
+    }
+    .ok_or_else(|| AppError::InvalidInput("没有进行中的登录，请重新登录".to_string()))?;
+    let data = PosEduClient::new(&domain)
+        .token(&code, &flow.code_verifier, &flow.redirect_uri)
+        .await?;
+    let tokens = TokenSet::from_response(&domain, &data, None)?;
+    let token = AccessToken {
+        access_token: tokens.access_token.clone(),
+        expires_at: tokens.expires_at,
+    };
+    session::set(tokens).await;
+    Ok(token)
 }
 
 #[tauri::command]
 pub async fn get_user_info(
+    app: AppHandle,
     access_token: String,
     domain: String,
-) -> Result<serde_json::Value, String> {
-    let client = reqwest::Client::new();
-    let userinfo_url = format!("https://{}.job3.posedu.cn/school/oauth/userinfo", domain);
-
-    let response = client
-        .get(userinfo_url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
+) -> Result<serde_json::Value, AppError> {
+    session::call(&app, &domain, &access_token, |client| async move {
+        client.userinfo().await
+    })
+    .await
+}
+
+// 退出登录，丢弃后端持有和本地保存的令牌
+#[tauri::command]
+pub async fn logout(app: AppHandle) -> Result<(), AppError> {
+    session::clear().await;
+    credentials::delete(&app.path().app_data_dir()?)
+}
+
+// 将当前令牌加密保存到应用数据目录，下次启动自动登录；
+// 提供 passphrase 时用口令加密，否则使用本机绑定的密钥
+#[tauri::command]
+pub async fn save_session(app: AppHandle, passphrase: Option<String>) -> Result<(), AppError> {
+    let tokens = session::current()
         .await
-        .map_err(|e| e.to_string())?;
+        .ok_or_else(|| AppError::Unauthorized("尚未登录".to_string()))?;
+    let data_dir = app.path().app_data_dir()?;
+    tokio::task::spawn_blocking(move || {
+        credentials::save(&data_dir, &tokens, passphrase.as_deref())
+    })
+    .await?
+}
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Get user info failed: {}", error_text));
-    }
+#[derive(Clone, Serialize, Debug)]
+pub struct RestoredSession {
+    pub domain: String,
+    pub access_token: String,
+    pub user_info: serde_json::Value,
+}
 
-    let user_data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+// 启动时恢复保存的登录：解密令牌并用 get_user_info 校验（过期时自动刷新）。
+// 没有保存或令牌已失效时返回 None，需要重新登录
+#[tauri::command]
+pub async fn restore_session(
+    app: AppHandle,
+    passphrase: Option<String>,
+) -> Result<Option<RestoredSession>, AppError> {
+    let data_dir = app.path().app_data_dir()?;
+    let Some(tokens) =
+        tokio::task::spawn_blocking(move || credentials::load(&data_dir, passphrase.as_deref()))
+            .await??
+    else {
+        return Ok(None);
+    };
 
-    Ok(user_data)
+    let domain = tokens.domain.clone();
+    let access_token = tokens.access_token.clone();
+    session::set(tokens).await;
+    let result = session::call(&app, &domain, &access_token, |client| async move {
+        client.userinfo().await
+    })
+    .await;
+
+    match result {
+        Ok(user_info) => {
+            let access_token = session::current()
+                .await
+                .map(|t| t.access_token)
+                .unwrap_or(access_token);
+            println!("🔓 Restored session for {}", domain);
+            Ok(Some(RestoredSession {
+                domain,
+                access_token,
+                user_info,
+            }))
+        }
+        // 刷新也失败时 session::call 已清除会话和保存的令牌
+        Err(AppError::Unauthorized(_)) => Ok(None),
+        Err(e) => {
+            session::clear().await;
+            Err(e)
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
@@ -347,286 +495,1243 @@ pub struct Stage {
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct Work {
-    id: i32,
-    title: String,
-    student_id: i32,
-    student_name: Option<String>,
-    college_name: Option<String>,
-    major_name: Option<String>,
-    class_name: Option<String>,
-    match_title: Option<String>,
-    stage_name: Option<String>,
-    check_status: i32,
-    createtime: i64,
-    files: Vec<WorkFile>,
+    pub id: i32,
+    pub title: String,
+    pub student_id: i32,
+    pub student_name: Option<String>,
+    pub college_name: Option<String>,
+    pub major_name: Option<String>,
+    pub class_name: Option<String>,
+    pub match_title: Option<String>,
+    pub stage_name: Option<String>,
+    pub check_status: i32,
+    pub createtime: i64,
+    pub files: Vec<WorkFile>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct UserContent {
-    url: String,
-    name: String,
+    pub url: String,
+    pub name: String,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct WorkFile {
-    id: i32,
-    element_label: String,
-    user_content: UserContent,
-    element_type: i32,
+    pub id: i32,
+    pub element_label: String,
+    pub user_content: UserContent,
+    pub element_type: i32,
 }
 
 #[tauri::command]
-pub async fn fetch_matches(access_token: String, domain: String) -> Result<Vec<Match>, String> {
+pub async fn fetch_matches(
+    app: AppHandle,
+    access_token: String,
+    domain: String,
+) -> Result<Vec<Match>, AppError> {
     println!("Fetching matches...");
-    let client = reqwest::Client::new();
-    let url = format!("https://{}.job3.posedu.cn/school/match_api/matches", domain);
-
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !response.status().is_success() {
-        return Err("获取比赛列表失败".to_string());
-    }
-
-    let data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-
-    if data["code"].as_i64() != Some(0) {
-        return Err(data["msg"].as_str().unwrap_or("未知错误").to_string());
-    }
-
-    let matches: Vec<Match> =
-        serde_json::from_value(data["data"].clone()).map_err(|e| e.to_string())?;
-
+    let matches = session::call(&app, &domain, &access_token, |client| async move {
+        client.matches().await
+    })
+    .await?;
     println!("Fetched {} matches", matches.len());
     Ok(matches)
 }
 
 #[tauri::command]
 pub async fn fetch_stages(
+    app: AppHandle,
     access_token: String,
     domain: String,
     match_id: i32,
-) -> Result<Vec<Stage>, String> {
+) -> Result<Vec<Stage>, AppError> {
     println!("Fetching stages for match {}...", match_id);
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://{}.job3.posedu.cn/school/match_api/stages?match_id={}",
-        domain, match_id
-    );
-
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !response.status().is_success() {
-        return Err("获取赛段列表失败".to_string());
-    }
-
-    let data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-
-    if data["code"].as_i64() != Some(0) {
-        return Err(data["msg"].as_str().unwrap_or("未知错误").to_string());
-    }
-
-    let stages: Vec<Stage> =
-        serde_json::from_value(data["data"].clone()).map_err(|e| e.to_string())?;
-
+    let stages = session::call(&app, &domain, &access_token, |client| async move {
+        client.stages(match_id).await
+    })
+    .await?;
     println!("Fetched {} stages", stages.len());
     Ok(stages)
 }
 
+#[derive(Clone, Serialize, Debug)]
+pub struct WorksProgressEvent {
+    pub match_id: i32,
+    pub stage_id: i32,
+    #[serde(flatten)]
+    pub progress: WorksProgress,
+}
+
 #[tauri::command]
 pub async fn fetch_works(
+    app: AppHandle,
     access_token: String,
     domain: String,
     match_id: i32,
     stage_id: i32,
-) -> Result<Vec<Work>, String> {
+    paging: Option<PagingOptions>,
+) -> Result<Vec<Work>, AppError> {
     println!(
         "Fetching works for match {} stage {}...",
         match_id, stage_id
     );
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://{}.job3.posedu.cn/school/match_api/works?match_id={}&stage_id={}",
-        domain, match_id, stage_id
-    );
+    let paging = paging.unwrap_or_default();
+    let on_progress = |progress: WorksProgress| {
+        let _ = app.emit(
+            "works://progress",
+            WorksProgressEvent {
+                match_id,
+                stage_id,
+                progress,
+            },
+        );
+    };
+    let (paging, on_progress) = (&paging, &on_progress);
+    let works = session::call(&app, &domain, &access_token, |client| async move {
+        client
+            .works_paged(match_id, stage_id, paging, on_progress)
+            .await
+    })
+    .await?;
+    println!("Fetched {} works", works.len());
+    Ok(works)
+}
 
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+// 按审核状态、学院/专业/班级、提交时间、标题/学生和是否有文件筛选作品
+#[tauri::command]
+pub async fn filter_works(works: Vec<Work>, filter: WorkFilter) -> Result<Vec<Work>, AppError> {
+    filter.validate().map_err(AppError::InvalidInput)?;
+    Ok(filter.apply(works))
+}
 
-    if !response.status().is_success() {
-        return Err("获取作品列表失败".to_string());
-    }
+#[tauri::command]
+pub async fn download_works(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
+    works: Vec<Work>,
+    batch_id: Option<String>,
+    save_path: String,
+    options: Option<DownloadOptions>,
+) -> Result<(), AppError> {
+    let options = Arc::new(options.unwrap_or_default());
+    let works = options.filter_works(works);
+    let batch_id = batch_id.or_else(new_batch_id);
+    let mut built = build_download_items(works, &batch_id, &save_path, &options)?;
+    apply_size_rules(&mut built, &options).await;
+
+    let semaphore = state.lock().await.get_semaphore();
+    let download_items = built.items.clone();
+    let info = BatchControlInfo::new(built, save_path, options);
+    start_batch(&app, semaphore, batch_id, info, download_items).await;
+
+    Ok(())
+}
+
+// 调用方未指定批次 id 时生成一个，批次结束时照常写入清单和报告
+fn new_batch_id() -> Option<String> {
+    Some(uuid::Uuid::new_v4().to_string())
+}
+
+// 预演下载：与 download_works 参数相同，返回计划下载的文件、大小和冲突，不写入任何文件
+#[tauri::command]
+pub async fn plan_download(
+    works: Vec<Work>,
+    batch_id: Option<String>,
+    save_path: String,
+    options: Option<DownloadOptions>,
+) -> Result<DownloadPlan, AppError> {
+    let options = options.unwrap_or_default();
+    let works = options.filter_works(works);
+    let mut built = build_download_items(works, &batch_id, &save_path, &options)?;
+    let mut remote = apply_size_rules(&mut built, &options).await;
+    let unchecked = built
+        .items
+        .iter()
+        .filter(|item| !remote.contains_key(&item.id));
+    let fetched = fetch_remote_info(unchecked).await;
+    remote.extend(fetched);
+
+    let items: Vec<PlannedItem> = built
+        .items
+        .into_iter()
+        .map(|item| {
+            let path = std::path::Path::new(&item.save_path).join(&item.filename);
+            let size = remote.get(&item.id).and_then(|r| r.size);
+            let existing = std::fs::metadata(&path)
+                .ok()
+                .filter(|m| m.is_file())
+                .map(|m| ExistingFile {
+                    size: m.len(),
+                    state: match size {
+                        Some(size) if m.len() == size => ExistingState::Complete,
+                        Some(size) if m.len() < size => ExistingState::Partial,
+                        Some(_) => ExistingState::Larger,
+                        None => ExistingState::Unknown,
+                    },
+                });
+            PlannedItem {
+                path: path.to_string_lossy().into_owned(),
+                size,
+                existing,
+                renamed_from: built.renamed.get(&item.id).cloned(),
+                item,
+            }
+        })
+        .collect();
+
+    let plan = DownloadPlan {
+        batch_id,
+        save_path,
+        total_size: items.iter().filter_map(|i| i.size).sum(),
+        unknown_sizes: items.iter().filter(|i| i.size.is_none()).count(),
+        conflicts: items.iter().filter(|i| i.existing.is_some()).count(),
+        collisions: items.iter().filter(|i| i.renamed_from.is_some()).count(),
+        items,
+        options,
+        rejected: built.rejected,
+        skipped: built.skipped,
+    };
+    println!(
+        "🗺️ Planned {} files ({} bytes known, {} unknown), {} conflicts, {} collisions",
+        plan.items.len(),
+        plan.total_size,
+        plan.unknown_sizes,
+        plan.conflicts,
+        plan.collisions
+    );
+    Ok(plan)
+}
 
-    let data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+// 按 plan_download 返回的计划开始下载；路径会重新校验，越出保存目录的项被拒绝
+#[tauri::command]
+pub async fn start_download_plan(
+    app: AppHandle,
+    state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
+    plan: DownloadPlan,
+) -> Result<(), AppError> {
+    let root = std::path::Path::new(&plan.save_path);
+    let batch_id = plan.batch_id.or_else(new_batch_id);
+    let mut built = BuiltItems {
+        items: Vec::new(),
+        rejected: plan.rejected,
+        skipped: plan.skipped,
+        renamed: HashMap::new(),
+    };
 
-    if data["code"].as_i64() != Some(0) {
-        return Err(data["msg"].as_str().unwrap_or("未知错误").to_string());
+    for planned in plan.items {
+        let mut item = planned.item;
+        item.batch_id = batch_id.clone();
+        let path = std::path::Path::new(&item.save_path).join(&item.filename);
+        let checked = path
+            .strip_prefix(root)
+            .map_err(|_| {
+                AppError::InvalidPath(format!(
+                    "{} is not inside {}",
+                    path.display(),
+                    root.display()
+                ))
+            })
+            .and_then(|relative| {
+                let parts: Vec<&str> = relative
+                    .iter()
+                    .map(|part| part.to_str().unwrap_or(""))
+                    .collect();
+                paths::resolve_within(root, &parts)
+            });
+        match checked {
+            Ok(_) => built.items.push(item),
+            Err(e) => {
+                eprintln!("🚫 Rejected {}: {}", item.filename, e);
+                let meta = item.meta.clone().unwrap_or_default();
+                built.rejected.push(ExcludedFile::rejected(meta, &e));
+            }
+        }
     }
 
-    let works: Vec<Work> =
-        serde_json::from_value(data["data"].clone()).map_err(|e| e.to_string())?;
+    let semaphore = state.lock().await.get_semaphore();
+    let download_items = built.items.clone();
+    let info = BatchControlInfo::new(built, plan.save_path, Arc::new(plan.options));
+    start_batch(&app, semaphore, batch_id, info, download_items).await;
 
-    println!("Fetched {} works", works.len());
-    Ok(works)
+    Ok(())
 }
 
+// 增量同步：与保存目录中的上次快照比较，只下载新增或变化的文件
 #[tauri::command]
-pub async fn download_works(
+pub async fn sync_works(
     app: AppHandle,
     state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
     works: Vec<Work>,
-    batch_id: Option<String>,
+    batch_id: String,
     save_path: String,
-) -> Result<(), String> {
+    options: Option<DownloadOptions>,
+    sync_options: Option<SyncOptions>,
+) -> Result<SyncReport, AppError> {
+    let options = Arc::new(options.unwrap_or_default());
+    let sync_options = sync_options.unwrap_or_default();
+    let root = std::path::PathBuf::from(&save_path);
+
+    // 被排除的审核状态的作品不下载，镜像模式下按已移除处理；
+    // 不符合筛选条件的作品只是本次不同步，本地文件和快照记录保持不变
+    let mut excluded: HashMap<i32, String> = HashMap::new();
+    let mut unfiltered = std::collections::HashSet::new();
+    let works: Vec<Work> = works
+        .into_iter()
+        .filter(|w| {
+            if sync_options.excluded_statuses.contains(&w.check_status) {
+                excluded.insert(w.id, format!("审核状态 {} 已被排除", w.check_status));
+                false
+            } else if options.filter.as_ref().is_some_and(|f| !f.matches(w)) {
+                unfiltered.insert(w.id);
+                false
+            } else {
+                true
+            }
+        })
+        .collect();
+
+    let mut built = build_download_items(works, &Some(batch_id.clone()), &save_path, &options)?;
+    let mut remote = apply_size_rules(&mut built, &options).await;
+    // 被文件规则跳过或因路径不安全被拒绝的文件仍属于作品，不按已移除处理
+    let held: std::collections::HashSet<(i32, i32)> = built
+        .skipped
+        .iter()
+        .chain(&built.rejected)
+        .map(|f| (f.work_id, f.file_id))
+        .collect();
+
+    let snapshot = sync::Snapshot::load(&root)
+        .map_err(|e| AppError::Io(format!("Failed to read sync snapshot: {}", e)))?;
+    let previous = snapshot.index();
+
+    // 只需要校验上次已同步过的文件
+    if sync_options.check_validators {
+        let unchecked = built.items.iter().filter(|item| {
+            !remote.contains_key(&item.id)
+                && item
+                    .meta
+                    .as_ref()
+                    .is_some_and(|m| previous.contains_key(&(m.work_id, m.file_id)))
+        });
+        let fetched = fetch_remote_info(unchecked).await;
+        remote.extend(fetched);
+    }
+
+    let items = built.items.clone();
+    let mut info = BatchControlInfo::new(built, save_path.clone(), options);
+    let mut state_sync = sync::SyncState {
+        match_id: sync_options.match_id,
+        stage_id: sync_options.stage_id,
+        ..Default::default()
+    };
+    let mut files = Vec::new();
+    let mut dispatch = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+
+    for item in items {
+        let meta = item.meta.clone().unwrap_or_default();
+        let key = (meta.work_id, meta.file_id);
+        seen.insert(key);
+        let old = previous.get(&key).copied();
+        let (status, reason) = sync::classify(&item, old, remote.get(&item.id));
+        let path = std::path::Path::new(&item.save_path).join(&item.filename);
+        let relative_path = relative_path_string(&root, &path);
+        let validators = remote.get(&item.id).cloned().unwrap_or_default();
+
+        let entry = sync::SnapshotEntry {
+            match_id: sync_options.match_id,
+            stage_id: sync_options.stage_id,
+            work_id: meta.work_id,
+            file_id: meta.file_id,
+            createtime: meta.createtime,
+            url: item.url.clone(),
+            relative_path: relative_path.clone(),
+            size: old.and_then(|e| e.size),
+            sha256: old.and_then(|e| e.sha256.clone()),
+            etag: validators.etag.or_else(|| old.and_then(|e| e.etag.clone())),
+            last_modified: validators
+                .last_modified
+                .or_else(|| old.and_then(|e| e.last_modified.clone())),
+        };
+
+        match status {
+            SyncStatus::Unchanged => {
+                info.results.insert(
+                    item.id.clone(),
+                    ItemResult {
+                        completed: true,
+                        error: None,
+                        warnings: Vec::new(),
+                        size: entry.size,
+                        sha256: entry.sha256.clone(),
+                    },
+                );
+                state_sync.unchanged.push(entry);
+            }
+            SyncStatus::Changed => {
+                // 旧版本先移到一旁，否则断点续传会把新内容接在旧文件后面；
+                // 新版本下载成功后才删除，失败或中断时恢复
+                // 暂存失败时旧文件仍在原处：本次不重新下载，保留旧记录，
+                // 作为被拒绝的文件报告，不影响其他文件
+                let backup = match sync::set_aside(&path) {
+                    Ok(backup) => backup,
+                    Err(e) => {
+                        let error =
+                            AppError::Io(format!("Failed to set aside {}: {}", path.display(), e));
+                        eprintln!("⚠️ {}", error);
+                        info.items.retain(|i| i.id != item.id);
+                        info.rejected.push(ExcludedFile::rejected(meta, &error));
+                        state_sync.unchanged.extend(old.cloned());
+                        continue;
+                    }
+                };
+                if let (Some(backup), Some(previous)) = (backup, old) {
+                    state_sync.replaced.insert(
+                        item.id.clone(),
+                        sync::Replaced {
+                            previous: previous.clone(),
+                            path: path.clone(),
+                            backup,
+                        },
+                    );
+                }
+                state_sync.pending.insert(item.id.clone(), entry);
+                dispatch.push(item.clone());
+            }
+            SyncStatus::Added => {
+                state_sync.pending.insert(item.id.clone(), entry);
+                dispatch.push(item.clone());
+            }
+        }
+
+        files.push(SyncFile {
+            work_id: meta.work_id,
+            file_id: meta.file_id,
+            student_id: meta.student_id,
+            name: meta.original_name,
+            relative_path,
+            status,
+            reason,
+        });
+    }
+
+    // 同一根目录下其他比赛/赛段的记录不参与比较，写快照时原样保留
+    let mut removed: Vec<sync::SnapshotEntry> = Vec::new();
+    for entry in &snapshot.files {
+        if !state_sync.in_scope(entry) || seen.contains(&(entry.work_id, entry.file_id)) {
+            continue;
+        }
+        if unfiltered.contains(&entry.work_id) || held.contains(&(entry.work_id, entry.file_id)) {
+            state_sync.unchanged.push(entry.clone());
+        } else {
+            removed.push(entry.clone());
+        }
+    }
+
+    // 镜像模式移走已移除作品的文件；未开启或移动失败时保留快照记录，之后仍可处理
+    let moved = if sync_options.mirror && !removed.is_empty() {
+        let entries: Vec<(sync::SnapshotEntry, String)> = removed
+            .iter()
+            .map(|entry| {
+                let reason = excluded
+                    .get(&entry.work_id)
+                    .cloned()
+                    .unwrap_or_else(|| "作品已不在作品列表中".to_string());
+                (entry.clone(), reason)
+            })
+            .collect();
+        let root = root.clone();
+        tokio::task::spawn_blocking(move || sync::move_to_removed(&root, &entries)).await?
+    } else {
+        Vec::new()
+    };
+    for entry in &removed {
+        let handled = moved
+            .iter()
+            .any(|m| m.work_id == entry.work_id && m.file_id == entry.file_id && m.error.is_none());
+        if !handled {
+            state_sync.unchanged.push(entry.clone());
+        }
+    }
+    for record in &moved {
+        match (&record.to, &record.error) {
+            (Some(to), _) => println!("🗃️ Moved {} -> {} ({})", record.from, to, record.reason),
+            (None, Some(error)) => eprintln!("⚠️ Failed to move {}: {}", record.from, error),
+            (None, None) => {}
+        }
+    }
+
+    let report = SyncReport {
+        batch_id: batch_id.clone(),
+        save_path,
+        added: count_status(&files, SyncStatus::Added),
+        changed: count_status(&files, SyncStatus::Changed),
+        unchanged: count_status(&files, SyncStatus::Unchanged),
+        removed,
+        moved,
+        files,
+    };
+    println!(
+        "🔁 Sync {}: {} added, {} changed, {} unchanged, {} removed, {} moved",
+        batch_id,
+        report.added,
+        report.changed,
+        report.unchanged,
+        report.removed.len(),
+        report.moved.iter().filter(|m| m.to.is_some()).count()
+    );
+
+    info.sync = Some(state_sync);
+    let semaphore = state.lock().await.get_semaphore();
+    start_batch(&app, semaphore, Some(batch_id), info, dispatch).await;
+
+    Ok(report)
+}
+
+fn count_status(files: &[SyncFile], status: SyncStatus) -> usize {
+    files.iter().filter(|f| f.status == status).count()
+}
+
+// 按文件规则的大小上下限跳过文件，返回 HEAD 得到的远程信息（下载项 id -> 信息）
+async fn apply_size_rules(
+    built: &mut BuiltItems,
+    options: &DownloadOptions,
+) -> HashMap<String, RemoteInfo> {
+    let Some(rules) = options.file_rules.as_ref().filter(|r| r.has_size_limits()) else {
+        return HashMap::new();
+    };
+
+    let remote = fetch_remote_info(&built.items).await;
+    let (items, skipped): (Vec<_>, Vec<_>) = std::mem::take(&mut built.items)
+        .into_iter()
+        .map(|item| {
+            let size = remote.get(&item.id).and_then(|r| r.size);
+            let reason = rules.size_skip_reason(size);
+            (item, reason)
+        })
+        .partition(|(_, reason)| reason.is_none());
+
+    built.items = items.into_iter().map(|(item, _)| item).collect();
+    built.skipped.extend(
+        skipped
+            .into_iter()
+            .map(|(item, reason)| ExcludedFile::from_item(&item, reason.unwrap_or_default())),
+    );
+    remote
+}
+
+// 并发发送 HEAD 请求，失败的文件不在结果中
+async fn fetch_remote_info<'a>(
+    items: impl IntoIterator<Item = &'a DownloadItem>,
+) -> HashMap<String, RemoteInfo> {
+    use futures::StreamExt;
+
+    let client = http::client();
+    futures::stream::iter(items)
+        .map(|item| {
+            let client = client.clone();
+            async move {
+                let info = downloader::head_info(&client, &item.url).await;
+                (item.id.clone(), info)
+            }
+        })
+        .buffer_unordered(8)
+        .filter_map(|(id, info)| async move {
+            match info {
+                Ok(info) => Some((id, info)),
+                Err(e) => {
+                    eprintln!("⚠️ {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+        .await
+}
+
+// 注册批次并在后台调度 dispatch 中的下载项；
+// info.results 中预先记录的项（如增量同步中未变化的文件）视为已完成
+async fn start_batch(
+    app: &AppHandle,
+    semaphore: Arc<tokio::sync::Semaphore>,
+    batch_id: Option<String>,
+    info: BatchControlInfo,
+    dispatch: Vec<DownloadItem>,
+) {
+    let save_path = info.save_path.clone();
+    let options = info.options.clone();
+    let rejected = info.rejected.clone();
+    let done = info.results.len() >= info.items.len();
+
+    // 关键修复：立即注册批次，确保 Stop/Pause 按钮立即可用
+    if let Some(ref bid) = batch_id {
+        println!(
+            "📦 Registering batch: {} (items: {}, to download: {})",
+            bid,
+            info.items.len(),
+            dispatch.len()
+        );
+        BATCH_TASKS.lock().await.insert(
+            bid.clone(),
+            BatchControlInfo {
+                finished: done,
+                ..info
+            },
+        );
+    }
+
+    for file in &rejected {
+        let _ = app.emit(
+            "download://warning",
+            ItemMessage {
+                id: None,
+                batch_id: batch_id.clone(),
+                message: format!("已拒绝 {}：{}", file.name, file.reason),
+                code: file.code.clone(),
+            },
+        );
+    }
+
+    // 没有需要下载的文件时批次直接结束，否则永远等不到最后一个结果
+    if done {
+        if let Some(ref bid) = batch_id {
+            finish_batch(app, bid, &save_path, &options).await;
+        }
+        return;
+    }
+
+    let app_clone = app.clone();
+    let batch_id_clone = batch_id.clone();
+
+    // 关键修复：将整个调度逻辑放入后台任务，避免阻塞主线程
+    tokio::spawn(async move {
+        let mut control_senders = Vec::new();
+
+        for item in dispatch {
+            // 检查批次状态：如果已暂停或删除，停止生成新任务
+            if let Some(ref bid) = batch_id_clone {
+                let tasks = BATCH_TASKS.lock().await;
+                if let Some(info) = tasks.get(bid) {
+                    if info.state == BatchState::Paused {
+                        println!("⏸️ Batch is paused, stopping dispatch loop: {}", bid);
+                        break;
+                    }
+                } else {
+                    // 批次已删除
+                    break;
+                }
+            }
+
+            // 在这里等待信号量，不会阻塞主线程 UI
+            let permit = match semaphore.clone().acquire_owned().await {
+                Ok(p) => p,
+                Err(_) => break, // 信号量关闭
+            };
+
+            // 再次检查状态（因为等待信号量可能花了很久）
+            if let Some(ref bid) = batch_id_clone {
+                let mut tasks = BATCH_TASKS.lock().await;
+                if let Some(info) = tasks.get_mut(bid) {
+                    if info.state == BatchState::Paused {
+                        // 释放信号量并退出
+                        drop(permit);
+                        break;
+                    }
+
+                    let app_handle = app_clone.clone();
+                    let client = http::client();
+                    let options = options.clone();
+                    let (tx, rx) = mpsc::channel(1);
+                    control_senders.push(tx);
+
+                    // 添加到 senders
+                    info.senders.push(control_senders.last().unwrap().clone());
+
+                    tokio::spawn(async move {
+                        let _permit = permit; // 任务结束自动释放
+                        run_batch_item(&client, &app_handle, item, &options, rx).await;
+                    });
+                } else {
+                    drop(permit);
+                    break;
+                }
+            } else {
+                // 单个文件下载（无 batch_id），保持原有逻辑
+                let app_handle = app_clone.clone();
+                let client = http::client();
+                let options = options.clone();
+                let (_tx, rx) = mpsc::channel(1); // dummy channel
+
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    run_batch_item(&client, &app_handle, item, &options, rx).await;
+                });
+            }
+        }
+    });
+}
+
+// 根据作品列表生成下载项（目录结构、文件名、作品信息）
+fn build_download_items(
+    works: Vec<Work>,
+    batch_id: &Option<String>,
+    save_path: &str,
+    options: &DownloadOptions,
+) -> Result<BuiltItems, AppError> {
+    let layout = parse_layout(options.layout.as_deref())?;
+    if let Some(ref filter) = options.filter {
+        filter.validate().map_err(AppError::InvalidInput)?;
+    }
+    if let Some(ref rules) = options.file_rules {
+        rules.validate().map_err(AppError::InvalidInput)?;
+    }
+    let file_template = options
+        .file_template
+        .as_deref()
+        .map(Template::parse_file_name)
+        .transpose()
+        .map_err(AppError::InvalidInput)?;
+
+    let total_files: usize = works.iter().map(|w| w.files.len()).sum();
+    let seq_width = total_files.to_string().len().max(2);
+    // 每个目录已使用的文件名，用于去重
+    let mut used_names: HashMap<String, std::collections::HashSet<String>> = HashMap::new();
+    let mut seq = 0;
     let mut download_items = Vec::new();
+    let mut rejected = Vec::new();
+    let mut skipped = Vec::new();
+    let mut renamed = HashMap::new();
+    let mut existing = paths::ExistingNames::new(options.unicode_form);
+
+    for work in works {
+        let segments = existing.resolve_segments(
+            std::path::Path::new(save_path),
+            &work_segments(&layout, &work, options),
+        );
+        let work_save_path = join_segments(save_path, &segments);
+
+        // 遍历每个文件
+        for (index, file) in work.files.iter().enumerate() {
+            let meta = item_meta(&work, file);
+            if let Some(reason) = options
+                .file_rules
+                .as_ref()
+                .and_then(|rules| rules.skip_reason(file))
+            {
+                skipped.push(ExcludedFile::new(meta, reason));
+                continue;
+            }
+            seq += 1;
+            let id = uuid::Uuid::new_v4().to_string();
+            let name = match file_template {
+                Some(ref template) => layout::render_file_name(
+                    template,
+                    &work,
+                    file,
+                    &layout::FilePosition {
+                        index: index + 1,
+                        seq,
+                        seq_width,
+                    },
+                    options.pinyin,
+                ),
+                None => file.user_content.name.clone(),
+            };
+            let wanted = sanitize::sanitize_file_name(
+                &sanitize::normalize(&name, options.unicode_form),
+                options.fs_profile,
+            );
+            let filename = layout::dedupe_name(
+                &wanted,
+                used_names.entry(work_save_path.clone()).or_default(),
+            );
+            if filename != wanted {
+                renamed.insert(id.clone(), wanted);
+            }
+            // 已下载过的文件可能以其他 Unicode 形式保存，沿用磁盘上的名称以便续传
+            let filename = existing.resolve(std::path::Path::new(&work_save_path), &filename);
+
+            // 服务器返回的名称不可信，最终路径必须留在 save_path 之内
+            let mut parts: Vec<&str> = segments.iter().map(String::as_str).collect();
+            parts.push(&filename);
+            if let Err(e) = paths::resolve_within(std::path::Path::new(save_path), &parts) {
+                eprintln!("🚫 Rejected {}: {}", file.user_content.name, e);
+                rejected.push(ExcludedFile::rejected(meta, &e));
+                continue;
+            }
+
+            download_items.push(DownloadItem {
+                id,
+                batch_id: batch_id.clone(),
+                url: file.user_content.url.clone(),
+                filename,
+                save_path: work_save_path.clone(),
+                meta: Some(meta),
+            });
+        }
+    }
+
+    Ok(BuiltItems {
+        items: download_items,
+        rejected,
+        skipped,
+        renamed,
+    })
+}
+
+// 下载项、清单和钩子使用的作品信息
+fn item_meta(work: &Work, file: &WorkFile) -> ItemMeta {
+    ItemMeta {
+        work_id: work.id,
+        work_title: work.title.clone(),
+        check_status: work.check_status,
+        createtime: work.createtime,
+        student_id: work.student_id,
+        student_name: work.student_name.clone(),
+        match_title: work.match_title.clone(),
+        stage_name: work.stage_name.clone(),
+        college_name: work.college_name.clone(),
+        major_name: work.major_name.clone(),
+        class_name: work.class_name.clone(),
+        file_id: file.id,
+        element_label: file.element_label.clone(),
+        element_type: file.element_type,
+        original_name: file.user_content.name.clone(),
+    }
+}
+
+fn parse_layout(template: Option<&str>) -> Result<Template, AppError> {
+    Template::parse(
+        template.unwrap_or(layout::DEFAULT_LAYOUT),
+        layout::WORK_FIELDS,
+    )
+    .map_err(AppError::InvalidInput)
+}
+
+// 按目录模板生成作品目录的各段名称，每段单独清理
+fn work_segments(layout: &Template, work: &Work, options: &DownloadOptions) -> Vec<String> {
+    layout
+        .render(&|name| layout::work_field(work, name), options.pinyin)
+        .iter()
+        .map(|segment| {
+            sanitize::sanitize_dir_name(
+                &sanitize::normalize(segment, options.unicode_form),
+                options.fs_profile,
+            )
+        })
+        .collect()
+}
+
+fn join_segments(save_path: &str, segments: &[String]) -> String {
+    let mut path = save_path.to_string();
+    for segment in segments {
+        path.push('/');
+        path.push_str(segment);
+    }
+    path
+}
+
+// 下载单个文件并记录结果，完成后执行 post-file 钩子
+async fn run_batch_item(
+    client: &reqwest::Client,
+    app: &AppHandle,
+    item: DownloadItem,
+    options: &DownloadOptions,
+    control_rx: mpsc::Receiver<BatchControl>,
+) {
+    let result = match download_file_with_control(client, app, item.clone(), control_rx).await {
+        Ok(ItemOutcome::Completed) => {
+            // 先计算摘要，钩子可能会移动或改写文件
+            let path = std::path::Path::new(&item.save_path).join(&item.filename);
+            let mut warnings = Vec::new();
+            let hashed = tokio::task::spawn_blocking(move || manifest::hash_file(&path))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r.map_err(|e| e.to_string()));
+            let (size, sha256) = match hashed {
+                Ok((size, hash)) => (Some(size), Some(hash)),
+                Err(e) => {
+                    warnings.push(format!("Failed to hash {}: {}", item.filename, e));
+                    (None, None)
+                }
+            };
+            warnings.extend(run_post_file_hook(app, &item, options).await);
+            ItemResult {
+                completed: true,
+                error: None,
+                warnings,
+                size,
+                sha256,
+            }
+        }
+        Ok(ItemOutcome::Interrupted) => return, // 暂停/停止的任务恢复后会重新下载
+        Err(e) => {
+            eprintln!("Download failed: {}", e);
+            let _ = app.emit(
+                "download://progress",
+                DownloadProgress {
+                    id: item.id.clone(),
+                    batch_id: item.batch_id.clone(),
+                    total: 0,
+                    current: 0,
+                    status: "error".to_string(),
+                },
+            );
+            ItemResult {
+                completed: false,
+                error: Some(e),
+                warnings: Vec::new(),
+                size: None,
+                sha256: None,
+            }
+        }
+    };
+
+    if let Some(ref batch_id) = item.batch_id {
+        record_item_result(app, batch_id, &item.id, result).await;
+    }
+}
+
+// 钩子失败只作为警告上报，不影响下载结果
+async fn run_post_file_hook(
+    app: &AppHandle,
+    item: &DownloadItem,
+    options: &DownloadOptions,
+) -> Vec<String> {
+    let Some(hooks) = options.hooks.as_ref() else {
+        return Vec::new();
+    };
+    let Some(command) = hooks.post_file.as_deref() else {
+        return Vec::new();
+    };
+
+    let path = std::path::Path::new(&item.save_path).join(&item.filename);
+    let meta = item.meta.clone().unwrap_or_default();
+    let env = [
+        ("MATCH_DOWNLOAD_HOOK", "post_file".to_string()),
+        ("MATCH_DOWNLOAD_PATH", path.to_string_lossy().into_owned()),
+        ("MATCH_DOWNLOAD_FILENAME", item.filename.clone()),
+        ("MATCH_DOWNLOAD_STUDENT_ID", meta.student_id.to_string()),
+        ("MATCH_DOWNLOAD_WORK_ID", meta.work_id.to_string()),
+        ("MATCH_DOWNLOAD_ELEMENT_LABEL", meta.element_label),
+        (
+            "MATCH_DOWNLOAD_BATCH_ID",
+            item.batch_id.clone().unwrap_or_default(),
+        ),
+    ];
+
+    let output = hooks::run_hook(
+        command,
+        std::path::Path::new(&item.save_path),
+        &env,
+        hooks.timeout(),
+    )
+    .await;
+    let warning = output.failure_message();
+
+    let _ = app.emit(
+        "download://hook",
+        HookEvent {
+            id: Some(item.id.clone()),
+            batch_id: item.batch_id.clone(),
+            hook: "post_file".to_string(),
+            output,
+        },
+    );
+
+    match warning {
+        Some(message) => {
+            eprintln!("⚠️ {}", message);
+            let _ = app.emit(
+                "download://warning",
+                ItemMessage {
+                    id: Some(item.id.clone()),
+                    batch_id: item.batch_id.clone(),
+                    message: message.clone(),
+                    code: None,
+                },
+            );
+            vec![message]
+        }
+        None => Vec::new(),
+    }
+}
+
+// 记录下载项结果，所有项都结束时触发批次收尾
+async fn record_item_result(app: &AppHandle, batch_id: &str, item_id: &str, result: ItemResult) {
+    let finished = {
+        let mut tasks = BATCH_TASKS.lock().await;
+        let Some(info) = tasks.get_mut(batch_id) else {
+            return;
+        };
+        info.results.insert(item_id.to_string(), result);
+
+        if !info.finished && info.results.len() >= info.items.len() {
+            info.finished = true;
+            Some((info.save_path.clone(), info.options.clone()))
+        } else {
+            None
+        }
+    };
 
-    for work in works {
-        // 构建目录路径: 比赛名称/赛段名称/学院/专业/班级/学生姓名_学号
-        let match_title = work.match_title.as_deref().unwrap_or("未命名比赛");
-        let stage_name = work.stage_name.as_deref().unwrap_or("未命名赛段");
-        let college_name = work.college_name.as_deref().unwrap_or("未知学院");
-        let major_name = work.major_name.as_deref().unwrap_or("未知专业");
-        let class_name = work.class_name.as_deref().unwrap_or("未知班级");
-        let student_name = work.student_name.as_deref().unwrap_or("未知学生");
-
-        // 学生文件夹名: 姓名_学号
-        let student_folder = format!("{}_{}", student_name, work.student_id);
-
-        // 构建完整的保存路径
-        let work_save_path = format!(
-            "{}/{}/{}/{}/{}/{}/{}",
-            save_path,
-            sanitize_filename(match_title),
-            sanitize_filename(stage_name),
-            sanitize_filename(college_name),
-            sanitize_filename(major_name),
-            sanitize_filename(class_name),
-            sanitize_filename(&student_folder)
-        );
+    if let Some((save_path, options)) = finished {
+        finish_batch(app, batch_id, &save_path, &options).await;
+    }
+}
 
-        // 遍历每个文件
-        for file in work.files {
-            let id = uuid::Uuid::new_v4().to_string();
-            let filename = sanitize_filename(&file.user_content.name);
+// 批次结束：写入清单，执行 post-batch 钩子并发送批次报告
+async fn finish_batch(app: &AppHandle, batch_id: &str, save_path: &str, options: &DownloadOptions) {
+    println!("🏁 Batch finished: {}", batch_id);
 
-            download_items.push(DownloadItem {
-                id,
-                batch_id: batch_id.clone(),
-                url: file.user_content.url,
-                filename,
-                save_path: work_save_path.clone(),
-            });
-        }
+    if let Err(message) = write_batch_manifest(batch_id).await {
+        add_batch_warning(app, batch_id, message).await;
+    }
+    if let Err(message) = write_sync_snapshot(app, batch_id).await {
+        add_batch_warning(app, batch_id, message).await;
     }
 
-    // 关键修复：立即注册批次，确保 Stop/Pause 按钮立即可用
-    if let Some(ref bid) = batch_id {
-        println!(
-            "📦 Registering batch: {} (items: {})",
-            bid,
-            download_items.len()
-        );
-        BATCH_TASKS.lock().await.insert(
-            bid.clone(),
-            BatchControlInfo {
-                senders: Vec::new(),
-                items: download_items.clone(),
-                state: BatchState::Running,
+    let hook = options
+        .hooks
+        .as_ref()
+        .and_then(|h| h.post_batch.as_deref().map(|cmd| (cmd, h.timeout())));
+
+    if let Some((command, timeout)) = hook {
+        let (total, completed, failed) = match build_batch_report(batch_id).await {
+            Some(report) => (report.total, report.completed, report.failed),
+            None => return,
+        };
+        let env = [
+            ("MATCH_DOWNLOAD_HOOK", "post_batch".to_string()),
+            ("MATCH_DOWNLOAD_PATH", save_path.to_string()),
+            ("MATCH_DOWNLOAD_BATCH_ID", batch_id.to_string()),
+            ("MATCH_DOWNLOAD_TOTAL", total.to_string()),
+            ("MATCH_DOWNLOAD_COMPLETED", completed.to_string()),
+            ("MATCH_DOWNLOAD_FAILED", failed.to_string()),
+        ];
+
+        let output = hooks::run_hook(command, std::path::Path::new(save_path), &env, timeout).await;
+
+        if let Some(message) = output.failure_message() {
+            add_batch_warning(app, batch_id, message).await;
+        }
+
+        let _ = app.emit(
+            "download://hook",
+            HookEvent {
+                id: None,
+                batch_id: Some(batch_id.to_string()),
+                hook: "post_batch".to_string(),
+                output,
             },
         );
     }
 
-    let manager = state.lock().await;
-    let semaphore = manager.get_semaphore();
-    let app_clone = app.clone();
-    let batch_id_clone = batch_id.clone();
+    if let Some(report) = build_batch_report(batch_id).await {
+        let _ = app.emit("download://batch-finished", report);
+    }
+}
 
-    // 关键修复：将整个调度逻辑放入后台任务，避免阻塞主线程
-    tokio::spawn(async move {
-        let mut control_senders = Vec::new();
+// 批次级别的警告：通知前端并记入批次报告
+async fn add_batch_warning(app: &AppHandle, batch_id: &str, message: String) {
+    eprintln!("⚠️ {}", message);
+    let _ = app.emit(
+        "download://warning",
+        ItemMessage {
+            id: None,
+            batch_id: Some(batch_id.to_string()),
+            message: message.clone(),
+            code: None,
+        },
+    );
+    if let Some(info) = BATCH_TASKS.lock().await.get_mut(batch_id) {
+        info.batch_warnings.push(message);
+    }
+}
 
-        for item in download_items {
-            // 检查批次状态：如果已暂停或删除，停止生成新任务
-            if let Some(ref bid) = batch_id_clone {
-                let tasks = BATCH_TASKS.lock().await;
-                if let Some(info) = tasks.get(bid) {
-                    if info.state == BatchState::Paused {
-                        println!("⏸️ Batch is paused, stopping dispatch loop: {}", bid);
-                        break;
-                    }
-                } else {
-                    // 批次已删除
-                    break;
-                }
-            }
+// 增量同步批次：用未变化的文件和本次下载成功的文件更新快照，
+// 并处理重新下载的文件暂存的旧版本
+async fn write_sync_snapshot(app: &AppHandle, batch_id: &str) -> Result<(), String> {
+    let job = BATCH_TASKS
+        .lock()
+        .await
+        .get(batch_id)
+        .and_then(SnapshotJob::new);
+    let Some(job) = job else {
+        return Ok(());
+    };
+    let (saved, warnings) = job.run().await;
+    for message in warnings {
+        add_batch_warning(app, batch_id, message).await;
+    }
+    saved
+}
 
-            // 在这里等待信号量，不会阻塞主线程 UI
-            let permit = match semaphore.clone().acquire_owned().await {
-                Ok(p) => p,
-                Err(_) => break, // 信号量关闭
-            };
+// 写快照所需的批次信息，取出后释放 BATCH_TASKS 锁再在阻塞线程中写入
+struct SnapshotJob {
+    root: std::path::PathBuf,
+    state: sync::SyncState,
+    completed: HashMap<String, (Option<u64>, Option<String>)>,
+}
 
-            // 再次检查状态（因为等待信号量可能花了很久）
-            if let Some(ref bid) = batch_id_clone {
-                let mut tasks = BATCH_TASKS.lock().await;
-                if let Some(info) = tasks.get_mut(bid) {
-                    if info.state == BatchState::Paused {
-                        // 释放信号量并退出
-                        drop(permit);
-                        break;
-                    }
+impl SnapshotJob {
+    fn new(info: &BatchControlInfo) -> Option<Self> {
+        let state = info.sync.clone()?;
+        let completed = info
+            .results
+            .iter()
+            .filter(|(_, result)| result.completed)
+            .map(|(id, result)| (id.clone(), (result.size, result.sha256.clone())))
+            .collect();
+        Some(Self {
+            root: std::path::PathBuf::from(&info.save_path),
+            state,
+            completed,
+        })
+    }
 
-                    let app_handle = app_clone.clone();
-                    let client = create_http_client();
-                    let (tx, rx) = mpsc::channel(1);
-                    control_senders.push(tx);
+    // 返回快照写入结果和暂存旧版本时的警告
+    async fn run(self) -> (Result<(), String>, Vec<String>) {
+        let joined = tokio::task::spawn_blocking(move || {
+            // 重新读取快照，保留批次进行期间其他赛段写入的记录；
+            // 读取失败时仍处理暂存的旧版本，但不覆盖快照
+            let previous = sync::Snapshot::load(&self.root);
+            let (snapshot, warnings) = self.state.finish(
+                previous.as_ref().unwrap_or(&Default::default()),
+                &self.completed,
+            );
+            (previous.and_then(|_| snapshot.save(&self.root)), warnings)
+        })
+        .await;
+        match joined {
+            Ok((saved, warnings)) => (
+                saved.map_err(|e| format!("Failed to write sync snapshot: {}", e)),
+                warnings,
+            ),
+            Err(e) => (Err(e.to_string()), Vec::new()),
+        }
+    }
+}
 
-                    // 添加到 senders
-                    info.senders.push(control_senders.last().unwrap().clone());
+// 根据批次记录生成清单行并合并写入批次根目录
+async fn write_batch_manifest(batch_id: &str) -> Result<(), String> {
+    let (root, rows) = {
+        let tasks = BATCH_TASKS.lock().await;
+        let Some(info) = tasks.get(batch_id) else {
+            return Ok(());
+        };
+        (
+            std::path::PathBuf::from(&info.save_path),
+            manifest_rows(batch_id, info),
+        )
+    };
+    write_manifest_rows(root, batch_id.to_string(), rows).await
+}
 
-                    tokio::spawn(async move {
-                        let _permit = permit; // 任务结束自动释放
-                        if let Err(e) =
-                            download_file_with_control(&client, &app_handle, item, rx).await
-                        {
-                            eprintln!("Download failed: {}", e);
-                        }
-                    });
-                } else {
-                    drop(permit);
-                    break;
-                }
-            } else {
-                // 单个文件下载（无 batch_id），保持原有逻辑
-                let app_handle = app_clone.clone();
-                let client = create_http_client();
-                let (_tx, rx) = mpsc::channel(1); // dummy channel
+async fn write_manifest_rows(
+    root: std::path::PathBuf,
+    batch_id: String,
+    rows: Vec<ManifestRow>,
+) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || manifest::write_manifest(&root, &batch_id, rows))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Failed to write manifest: {}", e))
+}
 
-                tokio::spawn(async move {
-                    let _permit = permit;
-                    if let Err(e) = download_file_with_control(&client, &app_handle, item, rx).await
-                    {
-                        eprintln!("Download failed: {}", e);
-                    }
-                });
+// 批次中每个文件一行，被拒绝和被文件规则跳过的文件也记录在内
+fn manifest_rows(batch_id: &str, info: &BatchControlInfo) -> Vec<ManifestRow> {
+    let root = std::path::Path::new(&info.save_path);
+    let mut rows: Vec<ManifestRow> = info
+        .items
+        .iter()
+        .map(|item| {
+            let result = info.results.get(&item.id);
+            let status = match result {
+                Some(r) if r.completed => manifest::STATUS_COMPLETED,
+                Some(_) => manifest::STATUS_ERROR,
+                None => manifest::STATUS_PENDING,
+            };
+            let path = std::path::Path::new(&item.save_path).join(&item.filename);
+            ManifestRow {
+                final_filename: item.filename.clone(),
+                relative_path: relative_path_string(root, &path),
+                size: result.and_then(|r| r.size),
+                sha256: result.and_then(|r| r.sha256.clone()),
+                error: result.and_then(|r| r.error.as_ref()).map(AppError::message),
+                ..ManifestRow::new(batch_id, item.meta.clone().unwrap_or_default(), status)
             }
+        })
+        .collect();
+
+    let excluded = [
+        (manifest::STATUS_REJECTED, &info.rejected),
+        (manifest::STATUS_SKIPPED, &info.skipped),
+    ];
+    for (status, files) in excluded {
+        rows.extend(files.iter().map(|file| ManifestRow {
+            error: Some(file.reason.clone()),
+            ..ManifestRow::new(batch_id, file.meta.clone(), status)
+        }));
+    }
+    rows
+}
+
+async fn build_batch_report(batch_id: &str) -> Option<BatchReport> {
+    let tasks = BATCH_TASKS.lock().await;
+    let info = tasks.get(batch_id)?;
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut completed = 0;
+    let mut failed = 0;
+    for item in &info.items {
+        let Some(result) = info.results.get(&item.id) else {
+            continue;
+        };
+        if result.completed {
+            completed += 1;
+        } else {
+            failed += 1;
         }
-    });
+        if let Some(ref error) = result.error {
+            errors.push(ItemMessage {
+                id: Some(item.id.clone()),
+                batch_id: Some(batch_id.to_string()),
+                message: error.message(),
+                code: Some(error.code().to_string()),
+            });
+        }
+        for message in &result.warnings {
+            warnings.push(ItemMessage {
+                id: Some(item.id.clone()),
+                batch_id: Some(batch_id.to_string()),
+                message: message.clone(),
+                code: None,
+            });
+        }
+    }
+    for message in &info.batch_warnings {
+        warnings.push(ItemMessage {
+            id: None,
+            batch_id: Some(batch_id.to_string()),
+            message: message.clone(),
+            code: None,
+        });
+    }
 
-    Ok(())
+    Some(BatchReport {
+        batch_id: batch_id.to_string(),
+        save_path: info.save_path.clone(),
+        total: info.items.len(),
+        completed,
+        failed,
+        pending: info.items.len() - completed - failed,
+        finished: info.finished,
+        errors,
+        warnings,
+        rejected: info.rejected.clone(),
+        skipped: info.skipped.clone(),
+    })
 }
 
 // 带控制通道的下载函数
@@ -635,7 +1740,7 @@ async fn download_file_with_control(
     app: &AppHandle,
     item: DownloadItem,
     mut control_rx: mpsc::Receiver<BatchControl>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<ItemOutcome, AppError> {
     const MAX_RETRIES: u32 = 3;
     let mut last_error = None;
 
@@ -653,12 +1758,12 @@ async fn download_file_with_control(
                     status: "stopped".to_string(),
                 },
             )?;
-            return Ok(());
+            return Ok(ItemOutcome::Interrupted);
         }
 
         match download_file_simple_with_control(client, app, &item, attempt, &mut control_rx).await
         {
-            Ok(_) => return Ok(()),
+            Ok(outcome) => return Ok(outcome),
             Err(e) => {
                 eprintln!(
                     "Download attempt {}/{} failed for {}: {}",
@@ -683,7 +1788,7 @@ async fn download_file_simple_with_control(
     item: &DownloadItem,
     attempt: u32,
     control_rx: &mut mpsc::Receiver<BatchControl>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<ItemOutcome, AppError> {
     use std::io::Write;
 
     let path = std::path::Path::new(&item.save_path).join(&item.filename);
@@ -713,10 +1818,7 @@ async fn download_file_simple_with_control(
         req_builder = req_builder.header("Range", format!("bytes={}-", downloaded_size));
     }
 
-    let res = req_builder
-        .send()
-        .await
-        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let res = http::send(req_builder).await?;
 
     // 处理 416 Range Not Satisfiable (说明文件可能已下载完)
     if res.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
@@ -732,11 +1834,15 @@ async fn download_file_simple_with_control(
                 status: "completed".to_string(),
             },
         )?;
-        return Ok(());
+        return Ok(ItemOutcome::Completed);
     }
 
     if !res.status().is_success() {
-        return Err(format!("HTTP error: {}", res.status()).into());
+        let status = res.status();
+        return Err(AppError::from_status(
+            status.as_u16(),
+            status.canonical_reason().unwrap_or_default().to_string(),
+        ));
     }
 
     let content_length = res.content_length().unwrap_or(0);
@@ -785,7 +1891,7 @@ async fn download_file_simple_with_control(
                             status: "stopped".to_string(),
                         },
                     )?;
-                    return Ok(());
+                    return Ok(ItemOutcome::Interrupted);
                 }
                 BatchControl::Pause => {
                     println!("Download paused for: {}", item.filename);
@@ -799,16 +1905,16 @@ async fn download_file_simple_with_control(
                             status: "paused".to_string(),
                         },
                     )?;
-                    return Ok(());
+                    return Ok(ItemOutcome::Interrupted);
                 }
             }
         }
 
-        let chunk = chunk_result.map_err(|e| format!("Failed to read chunk: {}", e))?;
+        let chunk = chunk_result?;
 
         writer
             .write_all(&chunk)
-            .map_err(|e| format!("Failed to write to file: {}", e))?;
+            .map_err(|e| AppError::Io(format!("Failed to write to file: {}", e)))?;
         current += chunk.len() as u64;
 
         if current - last_progress_update >= PROGRESS_UPDATE_THRESHOLD || current == total_size {
@@ -828,7 +1934,7 @@ async fn download_file_simple_with_control(
 
     writer
         .flush()
-        .map_err(|e| format!("Failed to flush file: {}", e))?;
+        .map_err(|e| AppError::Io(format!("Failed to flush file: {}", e)))?;
 
     println!("Successfully downloaded: {}", item.filename);
 
@@ -843,34 +1949,46 @@ async fn download_file_simple_with_control(
         },
     )?;
 
-    Ok(())
+    Ok(ItemOutcome::Completed)
 }
 
-fn create_http_client() -> reqwest::Client {
-    use std::time::Duration;
-    reqwest::Client::builder()
-        .pool_max_idle_per_host(20)
-        .pool_idle_timeout(Duration::from_secs(90))
-        .connect_timeout(Duration::from_secs(10))
-        .timeout(Duration::from_secs(300))
-        .tcp_keepalive(Duration::from_secs(60))
-        .build()
-        .unwrap()
-}
-
-// 辅助函数：清理文件名，移除不安全字符
-fn sanitize_filename(name: &str) -> String {
-    name.chars()
-        .map(|c| match c {
-            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
-            _ => c,
-        })
-        .collect()
+// 当前的 HTTP 客户端设置，不返回代理密码
+#[tauri::command]
+pub async fn get_http_settings() -> Result<HttpSettings, AppError> {
+    Ok(http::settings().redacted())
+}
+
+// 保存 HTTP 客户端设置并重建共享客户端；未传代理密码时沿用当前密码
+#[tauri::command]
+pub async fn set_http_settings(app: AppHandle, settings: HttpSettings) -> Result<(), AppError> {
+    let config_dir = app.path().app_config_dir()?;
+    let settings = settings.keep_password(&http::settings());
+    http::configure(settings.clone()).map_err(AppError::InvalidInput)?;
+    http::save_settings(&config_dir, &settings).map_err(AppError::Io)
+}
+
+// 平台接口地址设置
+#[tauri::command]
+pub async fn get_api_settings() -> Result<ApiSettings, AppError> {
+    Ok(api::settings())
+}
+
+#[tauri::command]
+pub async fn set_api_settings(app: AppHandle, settings: ApiSettings) -> Result<(), AppError> {
+    let config_dir = app.path().app_config_dir()?;
+    api::configure(settings.clone()).map_err(AppError::InvalidInput)?;
+    api::save_settings(&config_dir, &settings).map_err(AppError::Io)
+}
+
+// 连接复用统计
+#[tauri::command]
+pub async fn get_http_stats() -> Result<HttpStats, AppError> {
+    Ok(http::stats())
 }
 
 // 获取系统信息
 #[tauri::command]
-pub async fn get_system_info() -> Result<serde_json::Value, String> {
+pub async fn get_system_info() -> Result<serde_json::Value, AppError> {
     let cpu_count = num_cpus::get();
 
     Ok(serde_json::json!({
@@ -884,7 +2002,7 @@ pub async fn get_system_info() -> Result<serde_json::Value, String> {
 #[tauri::command]
 pub async fn pause_downloads(
     state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let manager = state.lock().await;
     manager.pause().await;
     Ok(())
@@ -895,7 +2013,7 @@ pub async fn pause_downloads(
 pub async fn resume_downloads(
     app: AppHandle,
     state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let manager = state.lock().await;
     manager.resume(&app).await;
     Ok(())
@@ -905,7 +2023,7 @@ pub async fn resume_downloads(
 #[tauri::command]
 pub async fn stop_downloads(
     state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let manager = state.lock().await;
     manager.stop().await;
     Ok(())
@@ -913,12 +2031,13 @@ pub async fn stop_downloads(
 
 // 停止单个批次
 #[tauri::command]
-pub async fn stop_batch(batch_id: String) -> Result<(), String> {
+pub async fn stop_batch(batch_id: String) -> Result<(), AppError> {
     println!("🛑 Attempting to stop batch: {}", batch_id);
 
     let mut tasks = BATCH_TASKS.lock().await;
 
     if let Some(info) = tasks.remove(&batch_id) {
+        drop(tasks);
         println!(
             "🛑 Stopping batch: {} (tasks: {})",
             batch_id,
@@ -926,22 +2045,51 @@ pub async fn stop_batch(batch_id: String) -> Result<(), String> {
         );
 
         // 向所有任务发送停止信号
-        for sender in info.senders {
+        for sender in &info.senders {
             let _ = sender.send(BatchControl::Stop).await;
         }
 
+        if !info.finished {
+            // 任务退出时丢弃接收端；等它们停止写文件后再恢复暂存的旧版本
+            let stopped = futures::future::join_all(info.senders.iter().map(|s| s.closed()));
+            if tokio::time::timeout(Duration::from_secs(10), stopped)
+                .await
+                .is_err()
+            {
+                eprintln!(
+                    "⚠️ Some downloads of batch {} did not stop in time",
+                    batch_id
+                );
+            }
+
+            // 停止的批次也写入清单，未完成的文件记为 pending
+            let rows = manifest_rows(&batch_id, &info);
+            let root = std::path::PathBuf::from(&info.save_path);
+            if let Err(message) = write_manifest_rows(root, batch_id.clone(), rows).await {
+                eprintln!("⚠️ {}", message);
+            }
+
+            // 同步批次：未完成的文件恢复旧版本，已完成的写入快照
+            if let Some(job) = SnapshotJob::new(&info) {
+                let (saved, warnings) = job.run().await;
+                for message in warnings.into_iter().chain(saved.err()) {
+                    eprintln!("⚠️ {}", message);
+                }
+            }
+        }
+
         println!("✅ Batch stopped successfully: {}", batch_id);
         Ok(())
     } else {
         let err_msg = format!("Batch {} not found in memory.", batch_id);
         eprintln!("❌ {}", err_msg);
-        Err(err_msg)
+        Err(AppError::NotFound(err_msg))
     }
 }
 
 // 暂停单个批次
 #[tauri::command]
-pub async fn pause_batch(batch_id: String) -> Result<(), String> {
+pub async fn pause_batch(batch_id: String) -> Result<(), AppError> {
     println!("⏸️ Attempting to pause batch: {}", batch_id);
 
     let mut tasks = BATCH_TASKS.lock().await;
@@ -967,7 +2115,7 @@ pub async fn pause_batch(batch_id: String) -> Result<(), String> {
 
         Ok(())
     } else {
-        Err(format!("Batch {} not found", batch_id))
+        Err(AppError::NotFound(format!("Batch {} not found", batch_id)))
     }
 }
 
@@ -977,16 +2125,45 @@ pub async fn resume_batch(
     app: AppHandle,
     state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
     batch_id: String,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     println!("▶️ Attempting to resume batch: {}", batch_id);
 
-    // 获取之前的下载项
-    let items_to_resume = {
-        let tasks = BATCH_TASKS.lock().await;
-        if let Some(info) = tasks.get(&batch_id) {
-            info.items.clone()
+    // 获取之前的下载项（已完成的不再重新下载，失败的重新尝试）
+    let (items_to_resume, options) = {
+        let mut tasks = BATCH_TASKS.lock().await;
+        if let Some(info) = tasks.get_mut(&batch_id) {
+            let pending: Vec<DownloadItem> = info
+                .items
+                .iter()
+                .filter(|item| !info.results.get(&item.id).is_some_and(|r| r.completed))
+                .cloned()
+                .collect();
+            for item in &pending {
+                info.results.remove(&item.id);
+            }
+            // 已结束的同步批次恢复了变化文件的旧版本，重新下载前要再次移开
+            let set_aside: Vec<std::path::PathBuf> = match info.sync {
+                Some(ref state) if info.finished => pending
+                    .iter()
+                    .filter_map(|item| state.replaced.get(&item.id))
+                    .map(|replaced| replaced.path.clone())
+                    .collect(),
+                _ => Vec::new(),
+            };
+            for path in set_aside {
+                if let Err(e) = sync::set_aside(&path) {
+                    eprintln!("⚠️ Failed to set aside {}: {}", path.display(), e);
+                }
+            }
+            if !pending.is_empty() {
+                info.finished = false;
+            }
+            (pending, info.options.clone())
         } else {
-            return Err(format!("Batch {} not found in memory history", batch_id));
+            return Err(AppError::NotFound(format!(
+                "Batch {} not found in memory history",
+                batch_id
+            )));
         }
     };
 
@@ -1004,7 +2181,7 @@ pub async fn resume_batch(
             info.state = BatchState::Running;
         } else {
             println!("❌ Resume failed: Batch {} not found", batch_id);
-            return Err(format!("Batch {} not found", batch_id));
+            return Err(AppError::NotFound(format!("Batch {} not found", batch_id)));
         }
     }
 
@@ -1071,7 +2248,8 @@ pub async fn resume_batch(
                     }
 
                     let app_handle = app_clone.clone();
-                    let client = create_http_client();
+                    let client = http::client();
+                    let options = options.clone();
                     let (tx, rx) = mpsc::channel(1);
                     control_senders.push(tx);
                     info.senders.push(control_senders.last().unwrap().clone());
@@ -1079,11 +2257,7 @@ pub async fn resume_batch(
                     let item_clone = item.clone();
                     tokio::spawn(async move {
                         let _permit = permit;
-                        if let Err(e) =
-                            download_file_with_control(&client, &app_handle, item_clone, rx).await
-                        {
-                            eprintln!("Resume download failed: {}", e);
-                        }
+                        run_batch_item(&client, &app_handle, item_clone, &options, rx).await;
                     });
                 } else {
                     drop(permit);
@@ -1097,11 +2271,356 @@ pub async fn resume_batch(
     Ok(())
 }
 
+// 获取批次报告（完成/失败数量、错误和钩子警告）
+#[tauri::command]
+pub async fn get_batch_report(batch_id: String) -> Result<BatchReport, AppError> {
+    build_batch_report(&batch_id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("Batch {} not found", batch_id)))
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct ArchiveProgress {
+    pub batch_id: String,
+    pub current: u64,
+    pub total: u64,
+}
+
+// 将批次已完成的文件导出为 zip，保留 比赛/赛段/学院/… 目录结构。
+// 批次已结束（不在内存中）时，从 save_path 下的清单读取该批次已完成的文件
+#[tauri::command]
+pub async fn export_batch_archive(
+    app: AppHandle,
+    batch_id: String,
+    dest: String,
+    options: Option<ArchiveOptions>,
+    save_path: Option<String>,
+) -> Result<ArchiveSummary, AppError> {
+    let options = options.unwrap_or_default();
+
+    // 只导出已完成的文件，按所在目录分组（每组即一个学生文件夹）：(目录, 文件名列表)
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    let mut add = |dir: String, filename: String| match groups.iter_mut().find(|(d, _)| *d == dir) {
+        Some((_, names)) => names.push(filename),
+        None => groups.push((dir, vec![filename])),
+    };
+    let in_memory = {
+        let tasks = BATCH_TASKS.lock().await;
+        tasks.get(&batch_id).map(|info| {
+            for item in &info.items {
+                if info.results.get(&item.id).is_some_and(|r| r.completed) {
+                    add(item.save_path.clone(), item.filename.clone());
+                }
+            }
+            info.save_path.clone()
+        })
+    };
+    let save_path = match (in_memory, save_path) {
+        (Some(save_path), _) => save_path,
+        (None, Some(save_path)) => {
+            let root = std::path::PathBuf::from(&save_path);
+            let id = batch_id.clone();
+            let files = tokio::task::spawn_blocking(move || manifest::completed_files(&root, &id))
+                .await??;
+            for relative in files {
+                let path = std::path::Path::new(&save_path).join(&relative);
+                if !path.is_file() {
+                    eprintln!("⚠️ Skipping missing file from manifest: {}", relative);
+                    continue;
+                }
+                if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+                    add(
+                        dir.to_string_lossy().into_owned(),
+                        name.to_string_lossy().into_owned(),
+                    );
+                }
+            }
+            save_path
+        }
+        (None, None) => {
+            return Err(AppError::NotFound(format!("Batch {} not found", batch_id)));
+        }
+    };
+
+    if groups.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "Batch {} has no completed files to export",
+            batch_id
+        )));
+    }
+
+    println!(
+        "🗜️ Exporting batch {} to {} (per student: {})",
+        batch_id, dest, options.per_student
+    );
+
+    let root = std::path::PathBuf::from(&save_path);
+    let dest = std::path::PathBuf::from(&dest);
+
+    let result = tokio::task::spawn_blocking(move || {
+        // 每个压缩包的 (目标文件, 条目列表)
+        let mut archives: Vec<(std::path::PathBuf, Vec<ArchiveEntry>)> = Vec::new();
+
+        if options.per_student {
+            let mut used_names = std::collections::HashSet::new();
+            for (dir, filenames) in &groups {
+                let dir = std::path::Path::new(dir);
+                let folder = dir
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "archive".to_string());
+
+                let mut name = format!("{}.zip", folder);
+                let mut n = 2;
+                while !used_names.insert(name.clone()) {
+                    name = format!("{}_{}.zip", folder, n);
+                    n += 1;
+                }
+
+                let entries = filenames
+                    .iter()
+                    .map(|filename| ArchiveEntry {
+                        source: dir.join(filename),
+                        name: format!("{}/{}", folder, filename),
+                    })
+                    .collect();
+                archives.push((dest.join(name), entries));
+            }
+        } else {
+            let mut entries: Vec<ArchiveEntry> = groups
+                .iter()
+                .flat_map(|(dir, filenames)| {
+                    filenames
+                        .iter()
+                        .map(move |filename| std::path::Path::new(dir).join(filename))
+                })
+                .map(|source| ArchiveEntry {
+                    name: relative_path_string(&root, &source),
+                    source,
+                })
+                .collect();
+
+            // 批次清单一并打包
+            for name in [manifest::MANIFEST_CSV, manifest::MANIFEST_JSON] {
+                let source = root.join(name);
+                if source.is_file() {
+                    entries.push(ArchiveEntry {
+                        source,
+                        name: name.to_string(),
+                    });
+                }
+            }
+            archives.push((dest.clone(), entries));
+        }
+
+        let total: u64 = archives
+            .iter()
+            .flat_map(|(_, entries)| entries)
+            .filter_map(|e| std::fs::metadata(&e.source).ok())
+            .map(|m| m.len())
+            .sum();
+
+        let mut summary = ArchiveSummary::default();
+        let mut current = 0u64;
+        let mut last_emit = 0u64;
+        let mut progress = |n: u64| {
+            current += n;
+            if current - last_emit >= 4 * 1024 * 1024 || current == total {
+                let _ = app.emit(
+                    "archive://progress",
+                    ArchiveProgress {
+                        batch_id: batch_id.clone(),
+                        current,
+                        total,
+                    },
+                );
+                last_emit = current;
+            }
+        };
+
+        for (path, entries) in &archives {
+            let (bytes, written) =
+                archive::write_archive(path, entries, &options, &mut progress)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            summary.bytes += bytes;
+            summary.entries += written;
+            summary.archives.push(path.to_string_lossy().into_owned());
+        }
+
+        Ok::<_, String>(summary)
+    })
+    .await?
+    .map_err(AppError::Io);
+
+    if let Ok(ref summary) = result {
+        println!(
+            "✅ Exported {} files into {} archive(s)",
+            summary.entries,
+            summary.archives.len()
+        );
+    }
+    result
+}
+
+// 相对于批次保存目录的路径，统一使用 '/'（压缩包条目、清单共用）
+fn relative_path_string(root: &std::path::Path, path: &std::path::Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// 导出作品列表为 Excel 工作簿；提供 save_path 时为已下载的文件加上本地链接
+#[tauri::command]
+pub async fn export_works_xlsx(
+    works: Vec<Work>,
+    dest: String,
+    save_path: Option<String>,
+    options: Option<DownloadOptions>,
+) -> Result<XlsxSummary, AppError> {
+    // 与下载时使用相同的目录模板和筛选条件，才能找到本地文件
+    let options = options.unwrap_or_default();
+    let works = options.filter_works(works);
+    println!("📊 Exporting {} works to {}", works.len(), dest);
+
+    let local_files: LocalFiles = match save_path {
+        Some(ref root) => build_download_items(works.clone(), &None, root, &options)?
+            .items
+            .into_iter()
+            .filter_map(|item| {
+                let meta = item.meta?;
+                let path = std::path::Path::new(&item.save_path).join(&item.filename);
+                path.is_file()
+                    .then_some(((meta.work_id, meta.file_id), path))
+            })
+            .collect(),
+        None => LocalFiles::new(),
+    };
+
+    tokio::task::spawn_blocking(move || {
+        xlsx::write_works_workbook(std::path::Path::new(&dest), &works, &local_files)
+    })
+    .await?
+    .map_err(|e| AppError::Io(format!("Failed to write workbook: {}", e)))
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct LayoutPreview {
+    pub work_id: i32,
+    pub student_id: i32,
+    pub path: String,
+    pub shared: bool, // 与其他作品落在同一目录
+}
+
+// 校验目录模板并预览生成的路径
+#[tauri::command]
+pub async fn preview_layout(
+    works: Vec<Work>,
+    save_path: String,
+    template: String,
+    options: Option<DownloadOptions>,
+    limit: Option<usize>,
+) -> Result<Vec<LayoutPreview>, AppError> {
+    let layout = parse_layout(Some(&template))?;
+    let options = options.unwrap_or_default();
+    let works = options.filter_works(works);
+
+    let mut previews: Vec<LayoutPreview> = works
+        .iter()
+        .map(|work| LayoutPreview {
+            work_id: work.id,
+            student_id: work.student_id,
+            path: join_segments(&save_path, &work_segments(&layout, work, &options)),
+            shared: false,
+        })
+        .collect();
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for preview in &previews {
+        *counts.entry(preview.path.as_str()).or_default() += 1;
+    }
+    let shared: std::collections::HashSet<String> = counts
+        .into_iter()
+        .filter(|(_, n)| *n > 1)
+        .map(|(path, _)| path.to_string())
+        .collect();
+    for preview in &mut previews {
+        preview.shared = shared.contains(&preview.path);
+    }
+
+    if let Some(limit) = limit {
+        previews.truncate(limit);
+    }
+    Ok(previews)
+}
+
+// 内置预设 + 用户保存的目录模板
+#[tauri::command]
+pub async fn list_layout_presets(app: AppHandle) -> Result<Vec<LayoutPreset>, AppError> {
+    let config_dir = app.path().app_config_dir()?;
+    let mut presets = layout::builtin_presets();
+    presets.extend(layout::load_presets(&config_dir));
+    Ok(presets)
+}
+
+#[tauri::command]
+pub async fn save_layout_preset(
+    app: AppHandle,
+    name: String,
+    template: String,
+) -> Result<Vec<LayoutPreset>, AppError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput(
+            "Preset name is required".to_string(),
+        ));
+    }
+    if layout::builtin_presets().iter().any(|p| p.name == name) {
+        return Err(AppError::InvalidInput(format!(
+            "Preset {} is built in and cannot be changed",
+            name
+        )));
+    }
+    parse_layout(Some(&template))?;
+
+    let config_dir = app.path().app_config_dir()?;
+    let mut presets = layout::load_presets(&config_dir);
+    match presets.iter_mut().find(|p| p.name == name) {
+        Some(preset) => preset.template = template,
+        None => presets.push(LayoutPreset {
+            name,
+            template,
+            builtin: false,
+        }),
+    }
+    layout::save_presets(&config_dir, &presets).map_err(AppError::Io)?;
+
+    list_layout_presets(app).await
+}
+
+#[tauri::command]
+pub async fn delete_layout_preset(
+    app: AppHandle,
+    name: String,
+) -> Result<Vec<LayoutPreset>, AppError> {
+    let config_dir = app.path().app_config_dir()?;
+    let mut presets = layout::load_presets(&config_dir);
+    presets.retain(|p| p.name != name);
+    layout::save_presets(&config_dir, &presets).map_err(AppError::Io)?;
+
+    list_layout_presets(app).await
+}
+
 // 获取下载管理器状态
 #[tauri::command]
 pub async fn get_download_state(
     state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     let manager = state.lock().await;
     let state = manager.get_state().await;
     Ok(format!("{:?}", state))
@@ -1111,20 +2630,20 @@ pub async fn get_download_state(
 #[tauri::command]
 pub async fn get_current_concurrency(
     state: tauri::State<'_, Arc<Mutex<DownloadManager>>>,
-) -> Result<usize, String> {
+) -> Result<usize, AppError> {
     let manager = state.lock().await;
     Ok(manager.get_concurrency())
 }
 
 // 打开文件夹
 #[tauri::command]
-pub async fn open_folder(path: String) -> Result<(), String> {
+pub async fn open_folder(path: String) -> Result<(), AppError> {
     #[cfg(target_os = "macos")]
     {
         std::process::Command::new("open")
             .arg(&path)
             .spawn()
-            .map_err(|e| format!("Failed to open folder: {}", e))?;
+            .map_err(|e| AppError::Io(format!("Failed to open folder: {}", e)))?;
     }
 
     #[cfg(target_os = "windows")]
@@ -1132,7 +2651,7 @@ pub async fn open_folder(path: String) -> Result<(), String> {
         std::process::Command::new("explorer")
             .arg(&path)
             .spawn()
-            .map_err(|e| format!("Failed to open folder: {}", e))?;
+            .map_err(|e| AppError::Io(format!("Failed to open folder: {}", e)))?;
     }
 
     #[cfg(target_os = "linux")]
@@ -1140,7 +2659,7 @@ pub async fn open_folder(path: String) -> Result<(), String> {
         std::process::Command::new("xdg-open")
             .arg(&path)
             .spawn()
-            .map_err(|e| format!("Failed to open folder: {}", e))?;
+            .map_err(|e| AppError::Io(format!("Failed to open folder: {}", e)))?;
     }
 
     Ok(())
@@ -0,0 +1,196 @@
+use crate::error::AppError;
+use crate::session::TokenSet;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+// 加密保存的令牌，位于应用数据目录
+const STORE_FILE: &str = "credentials.json";
+const STORE_VERSION: u32 = 1;
+
+// 本机密钥由机器 ID 派生，拷到其他电脑上无法解密
+const MACHINE_KEY_CONTEXT: &str = "com.wlzj.match-downloader/credentials/v1";
+const MACHINE_KEY_ROUNDS: u32 = 100_000;
+const PASSPHRASE_ROUNDS: u32 = 600_000;
+
+// 最近一次保存/读取使用的密钥，令牌刷新后用它重新写入
+static CURRENT_KEY: once_cell::sync::Lazy<Mutex<Option<StoreKey>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    Machine,    // 机器绑定密钥，启动时自动解密
+    Passphrase, // 用户口令，启动时需要输入
+}
+
+// 磁盘上的格式，二进制字段均为 hex
+#[derive(Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    key_source: KeySource,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Clone)]
+struct StoreKey {
+    source: KeySource,
+    salt: [u8; 16],
+    key: [u8; 32],
+}
+
+impl StoreKey {
+    fn derive(
+        source: KeySource,
+        passphrase: Option<&str>,
+        salt: [u8; 16],
+    ) -> Result<Self, AppError> {
+        let mut key = [0u8; 32];
+        match (source, passphrase) {
+            (KeySource::Machine, _) => {
+                let id = machine_uid::get()
+                    .map_err(|e| AppError::Internal(format!("Failed to read machine id: {}", e)))?;
+                let secret = format!("{}:{}", MACHINE_KEY_CONTEXT, id);
+                pbkdf2::pbkdf2_hmac::<sha2::Sha256>(
+                    secret.as_bytes(),
+                    &salt,
+                    MACHINE_KEY_ROUNDS,
+                    &mut key,
+                );
+            }
+            (KeySource::Passphrase, Some(passphrase)) if !passphrase.is_empty() => {
+                pbkdf2::pbkdf2_hmac::<sha2::Sha256>(
+                    passphrase.as_bytes(),
+                    &salt,
+                    PASSPHRASE_ROUNDS,
+                    &mut key,
+                );
+            }
+            (KeySource::Passphrase, _) => {
+                return Err(AppError::PassphraseRequired(
+                    "需要输入口令才能读取保存的登录信息".to_string(),
+                ))
+            }
+        }
+        Ok(Self { source, salt, key })
+    }
+}
+
+// 加密保存令牌；提供 passphrase 时用口令派生密钥，否则使用本机密钥
+pub fn save(data_dir: &Path, tokens: &TokenSet, passphrase: Option<&str>) -> Result<(), AppError> {
+    let source = match passphrase {
+        Some(_) => KeySource::Passphrase,
+        None => KeySource::Machine,
+    };
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let key = StoreKey::derive(source, passphrase, salt)?;
+    write(data_dir, tokens, &key)?;
+    *CURRENT_KEY.lock().unwrap() = Some(key);
+    Ok(())
+}
+
+// 令牌刷新后更新保存的文件；本次运行没有保存或读取过时不写入
+pub fn update(data_dir: &Path, tokens: &TokenSet) -> Result<(), AppError> {
+    let key = CURRENT_KEY.lock().unwrap().clone();
+    match key {
+        Some(key) => write(data_dir, tokens, &key),
+        None => Ok(()),
+    }
+}
+
+// 读取保存的令牌，没有保存时返回 None；口令模式下缺少或口令错误返回 PassphraseRequired
+pub fn load(data_dir: &Path, passphrase: Option<&str>) -> Result<Option<TokenSet>, AppError> {
+    let text = match std::fs::read_to_string(data_dir.join(STORE_FILE)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let file: StoreFile = serde_json::from_str(&text)?;
+    if file.version != STORE_VERSION {
+        return Err(AppError::Decode(format!(
+            "Unsupported credential store version {}",
+            file.version
+        )));
+    }
+
+    let salt: [u8; 16] = decode_hex(&file.salt)?;
+    let nonce: [u8; 12] = decode_hex(&file.nonce)?;
+    let ciphertext = hex::decode(&file.ciphertext).map_err(|e| AppError::Decode(e.to_string()))?;
+    let key = StoreKey::derive(file.key_source, passphrase, salt)?;
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| match file.key_source {
+            KeySource::Passphrase => AppError::PassphraseRequired("口令错误".to_string()),
+            KeySource::Machine => AppError::Decode("保存的登录信息无法在本机解密".to_string()),
+        })?;
+    let tokens = serde_json::from_slice(&plaintext)?;
+    *CURRENT_KEY.lock().unwrap() = Some(key);
+    Ok(Some(tokens))
+}
+
+pub fn delete(data_dir: &Path) -> Result<(), AppError> {
+    *CURRENT_KEY.lock().unwrap() = None;
+    match std::fs::remove_file(data_dir.join(STORE_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// 每次写入使用新的随机 nonce，先写临时文件再替换
+fn write(data_dir: &Path, tokens: &TokenSet, key: &StoreKey) -> Result<(), AppError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(tokens)?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_ref())
+        .map_err(|e| AppError::Internal(format!("Failed to encrypt credentials: {}", e)))?;
+
+    let file = StoreFile {
+        version: STORE_VERSION,
+        key_source: key.source,
+        salt: hex::encode(key.salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    };
+    std::fs::create_dir_all(data_dir)?;
+    let path = data_dir.join(STORE_FILE);
+    let tmp = path.with_extension("json.tmp");
+    write_private(&tmp, serde_json::to_string_pretty(&file)?.as_bytes())?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+// 凭据文件只允许当前用户读写；Unix 上创建时即为 0600，避免改名前被其他用户读到
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    // 残留的临时文件可能带着旧权限，mode 只在新建时生效
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+fn decode_hex<const N: usize>(text: &str) -> Result<[u8; N], AppError> {
+    let mut bytes = [0u8; N];
+    hex::decode_to_slice(text, &mut bytes).map_err(|e| AppError::Decode(e.to_string()))?;
+    Ok(bytes)
+}
@@ -1,3 +1,4 @@
+use crate::http;
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -14,6 +15,28 @@ pub struct DownloadItem {
     pub url: String,
     pub filename: String,
     pub save_path: String,
+    #[serde(default)]
+    pub meta: Option<ItemMeta>,
+}
+
+// 下载项对应的作品信息，供钩子、清单等后续处理使用
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct ItemMeta {
+    pub work_id: i32,
+    pub work_title: String,
+    pub check_status: i32,
+    pub createtime: i64,
+    pub student_id: i32,
+    pub student_name: Option<String>,
+    pub match_title: Option<String>,
+    pub stage_name: Option<String>,
+    pub college_name: Option<String>,
+    pub major_name: Option<String>,
+    pub class_name: Option<String>,
+    pub file_id: i32,
+    pub element_label: String,
+    pub element_type: i32,
+    pub original_name: String,
 }
 
 #[derive(Clone, Serialize, Debug)]
@@ -120,7 +143,7 @@ impl DownloadManager {
         progress_map: Arc<Mutex<HashMap<String, u64>>>,
         app: AppHandle,
     ) {
-        let client = create_http_client();
+        let client = http::client();
 
         loop {
             // 每次循环都重新检查状态（不持有锁）
@@ -208,15 +231,33 @@ impl DownloadManager {
     }
 }
 
-fn create_http_client() -> reqwest::Client {
-    reqwest::Client::builder()
-        .pool_max_idle_per_host(20)
-        .pool_idle_timeout(Duration::from_secs(90))
-        .connect_timeout(Duration::from_secs(10))
-        .timeout(Duration::from_secs(300))
-        .tcp_keepalive(Duration::from_secs(60))
-        .build()
-        .unwrap()
+// HEAD 请求得到的远程文件信息，用于比较文件是否变化
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct RemoteInfo {
+    pub size: Option<u64>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+pub async fn head_info(client: &reqwest::Client, url: &str) -> Result<RemoteInfo, String> {
+    let res = http::send(client.head(url))
+        .await
+        .map_err(|e| format!("HEAD request failed: {}", e))?;
+    if !res.status().is_success() {
+        return Err(format!("HEAD request failed: {}", res.status()));
+    }
+
+    let header = |name: reqwest::header::HeaderName| {
+        res.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    Ok(RemoteInfo {
+        size: header(reqwest::header::CONTENT_LENGTH).and_then(|v| v.parse().ok()),
+        etag: header(reqwest::header::ETAG),
+        last_modified: header(reqwest::header::LAST_MODIFIED),
+    })
 }
 
 async fn download_file_with_resume(
@@ -303,8 +344,7 @@ async fn download_file_attempt_resumable(
         request = request.header("Range", format!("bytes={}-", downloaded));
     }
 
-    let res = request
-        .send()
+    let res = http::send(request)
         .await
         .map_err(|e| format!("Failed to send request: {}", e))?;
 
@@ -0,0 +1,159 @@
+use serde::{Serialize, Serializer};
+
+// 命令返回给前端的错误，序列化为
+// {code, message, status, retryable, platform_code}，前端按 code 区分处理
+#[derive(Debug, Clone)]
+pub enum AppError {
+    Network(String),
+    Timeout(String),
+    Unauthorized(String),                  // 令牌失效，需要重新登录
+    Http { status: u16, message: String }, // 其他非 2xx 响应
+    Platform { code: i64, msg: String },   // 平台返回 code != 0
+    Decode(String),
+    Io(String),
+    InvalidPath(String),
+    InvalidInput(String),
+    NotFound(String),
+    Cancelled(String),
+    PassphraseRequired(String), // 保存的登录信息需要口令解密
+    Internal(String),
+}
+
+impl AppError {
+    // 稳定的错误码，前端据此判断，不要随意修改
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Network(_) => "network",
+            AppError::Timeout(_) => "timeout",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Http { .. } => "http",
+            AppError::Platform { .. } => "platform_error",
+            AppError::Decode(_) => "decode",
+            AppError::Io(_) => "io",
+            AppError::InvalidPath(_) => "invalid_path",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::NotFound(_) => "not_found",
+            AppError::Cancelled(_) => "cancelled",
+            AppError::PassphraseRequired(_) => "passphrase_required",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    // 对应的 HTTP 状态码（有的话）
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            AppError::Unauthorized(_) => Some(401),
+            AppError::Http { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    // 稍后重试可能成功：网络错误、超时、429 和 5xx
+    pub fn retryable(&self) -> bool {
+        match self {
+            AppError::Network(_) | AppError::Timeout(_) => true,
+            AppError::Http { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    // 从 HTTP 状态码和响应内容构造错误
+    pub fn from_status(status: u16, message: String) -> Self {
+        match status {
+            401 => AppError::Unauthorized(message),
+            _ => AppError::Http { status, message },
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AppError::Network(e) => format!("网络请求失败: {}", e),
+            AppError::Timeout(e) => format!("请求超时: {}", e),
+            AppError::Unauthorized(e) if e.is_empty() => "登录已失效，请重新登录".to_string(),
+            AppError::Unauthorized(e) => format!("登录已失效，请重新登录: {}", e),
+            AppError::Http { status, message } if message.is_empty() => format!("HTTP {}", status),
+            AppError::Http { status, message } => format!("HTTP {}: {}", status, message),
+            AppError::Platform { msg, .. } => msg.clone(),
+            AppError::Decode(e) => format!("响应解析失败: {}", e),
+            AppError::Io(e)
+            | AppError::InvalidPath(e)
+            | AppError::InvalidInput(e)
+            | AppError::NotFound(e)
+            | AppError::Cancelled(e)
+            | AppError::PassphraseRequired(e)
+            | AppError::Internal(e) => e.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Payload {
+            code: &'static str,
+            message: String,
+            status: Option<u16>,
+            retryable: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            platform_code: Option<i64>,
+        }
+
+        Payload {
+            code: self.code(),
+            message: self.message(),
+            status: self.status(),
+            retryable: self.retryable(),
+            platform_code: match self {
+                AppError::Platform { code, .. } => Some(*code),
+                _ => None,
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            AppError::Timeout(e.to_string())
+        } else if let Some(status) = e.status() {
+            AppError::from_status(status.as_u16(), e.to_string())
+        } else if e.is_decode() {
+            AppError::Decode(e.to_string())
+        } else {
+            AppError::Network(e.to_string())
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Io(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Decode(e.to_string())
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(e: tauri::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for AppError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
@@ -0,0 +1,153 @@
+use crate::commands::{Work, WorkFile};
+use serde::{Deserialize, Serialize};
+
+// 作品筛选条件，空的条件不参与筛选；download_works、sync_works 和导出共用
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct WorkFilter {
+    pub check_status: Vec<i32>,
+    pub colleges: Vec<String>,
+    pub majors: Vec<String>,
+    pub classes: Vec<String>,
+    pub created_from: Option<i64>, // createtime 下限（含），Unix 秒
+    pub created_to: Option<i64>,   // createtime 上限（含），Unix 秒
+    pub title: Option<String>,     // 标题包含，忽略大小写
+    pub student: Option<String>,   // 姓名或学号包含
+    pub has_files: Option<bool>,
+}
+
+impl WorkFilter {
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(from), Some(to)) = (self.created_from, self.created_to) {
+            if from > to {
+                return Err(format!(
+                    "Invalid submission time range: {} is after {}",
+                    from, to
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, work: &Work) -> bool {
+        (self.check_status.is_empty() || self.check_status.contains(&work.check_status))
+            && in_list(&self.colleges, &work.college_name)
+            && in_list(&self.majors, &work.major_name)
+            && in_list(&self.classes, &work.class_name)
+            && self.created_from.is_none_or(|from| work.createtime >= from)
+            && self.created_to.is_none_or(|to| work.createtime <= to)
+            && query(&self.title).is_none_or(|q| contains(&work.title, q))
+            && self.matches_student(work)
+            && self
+                .has_files
+                .is_none_or(|has_files| has_files != work.files.is_empty())
+    }
+
+    pub fn apply(&self, works: Vec<Work>) -> Vec<Work> {
+        works
+            .into_iter()
+            .filter(|work| self.matches(work))
+            .collect()
+    }
+
+    fn matches_student(&self, work: &Work) -> bool {
+        match query(&self.student) {
+            Some(query) => {
+                contains(work.student_name.as_deref().unwrap_or(""), query)
+                    || work.student_id.to_string().contains(query)
+            }
+            None => true,
+        }
+    }
+}
+
+// 名称按去掉首尾空白后完全相同匹配
+fn in_list(names: &[String], value: &Option<String>) -> bool {
+    names.is_empty()
+        || value
+            .as_deref()
+            .is_some_and(|value| names.iter().any(|name| name.trim() == value.trim()))
+}
+
+fn query(text: &Option<String>) -> Option<&str> {
+    text.as_deref().map(str::trim).filter(|q| !q.is_empty())
+}
+
+fn contains(text: &str, query: &str) -> bool {
+    text.to_lowercase().contains(&query.to_lowercase())
+}
+
+// 文件级规则：按元素名称、元素类型、文件名通配符和大小选择作品中的文件。
+// include 为空表示不限，exclude 优先；大小来自 HEAD 请求，未知时不按大小跳过
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct FileRules {
+    pub include_labels: Vec<String>,
+    pub exclude_labels: Vec<String>,
+    pub include_types: Vec<i32>,
+    pub exclude_types: Vec<i32>,
+    pub include_globs: Vec<String>, // 按原文件名匹配，忽略大小写，如 "*.mp4"
+    pub exclude_globs: Vec<String>,
+    pub min_size: Option<u64>, // 字节
+    pub max_size: Option<u64>,
+}
+
+impl FileRules {
+    pub fn validate(&self) -> Result<(), String> {
+        for pattern in self.include_globs.iter().chain(&self.exclude_globs) {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format!("Invalid file pattern {:?}: {}", pattern, e))?;
+        }
+        Ok(())
+    }
+
+    // 返回跳过原因，None 表示保留
+    pub fn skip_reason(&self, file: &WorkFile) -> Option<String> {
+        let label = file.element_label.trim();
+        let name = file.user_content.name.as_str();
+
+        if self.exclude_labels.iter().any(|l| l.trim() == label) {
+            return Some(format!("元素「{}」已排除", label));
+        }
+        if !self.include_labels.is_empty() && !self.include_labels.iter().any(|l| l.trim() == label)
+        {
+            return Some(format!("元素「{}」不在包含列表中", label));
+        }
+        if self.exclude_types.contains(&file.element_type) {
+            return Some(format!("元素类型 {} 已排除", file.element_type));
+        }
+        if !self.include_types.is_empty() && !self.include_types.contains(&file.element_type) {
+            return Some(format!("元素类型 {} 不在包含列表中", file.element_type));
+        }
+        if let Some(pattern) = self.exclude_globs.iter().find(|p| glob_matches(p, name)) {
+            return Some(format!("文件名匹配排除规则 {}", pattern));
+        }
+        if !self.include_globs.is_empty()
+            && !self.include_globs.iter().any(|p| glob_matches(p, name))
+        {
+            return Some("文件名不匹配包含规则".to_string());
+        }
+        None
+    }
+
+    pub fn has_size_limits(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+
+    pub fn size_skip_reason(&self, size: Option<u64>) -> Option<String> {
+        let size = size?;
+        match (self.min_size, self.max_size) {
+            (Some(min), _) if size < min => Some(format!("文件大小 {} 字节小于下限 {}", size, min)),
+            (_, Some(max)) if size > max => Some(format!("文件大小 {} 字节超过上限 {}", size, max)),
+            _ => None,
+        }
+    }
+}
+
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    glob::Pattern::new(pattern).is_ok_and(|p| p.matches_with(name, options))
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 120;
+// 捕获输出的最大长度，避免脚本刷屏撑爆事件
+const MAX_CAPTURED_OUTPUT: usize = 8 * 1024;
+
+// 用户自定义的下载后钩子，命令通过系统 shell 执行
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct HookConfig {
+    pub post_file: Option<String>,  // 每个文件下载完成后执行
+    pub post_batch: Option<String>, // 整个批次结束后执行
+    pub timeout_secs: Option<u64>,
+}
+
+impl HookConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS))
+    }
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct HookOutput {
+    pub command: String,
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookOutput {
+    // 钩子失败时给出可读的警告信息，成功返回 None
+    pub fn failure_message(&self) -> Option<String> {
+        if self.success {
+            return None;
+        }
+
+        let reason = if self.timed_out {
+            "timed out".to_string()
+        } else {
+            match self.exit_code {
+                Some(code) => format!("exited with code {}", code),
+                None => "failed to run".to_string(),
+            }
+        };
+
+        let detail = self.stderr.trim();
+        if detail.is_empty() {
+            Some(format!("Hook `{}` {}", self.command, reason))
+        } else {
+            Some(format!("Hook `{}` {}: {}", self.command, reason, detail))
+        }
+    }
+}
+
+// 执行钩子命令，超时后强制结束子进程
+pub async fn run_hook(
+    command: &str,
+    cwd: &Path,
+    env: &[(&str, String)],
+    timeout: Duration,
+) -> HookOutput {
+    let mut cmd = shell_command(command);
+    cmd.envs(env.iter().map(|(k, v)| (*k, v.as_str())))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    if cwd.is_dir() {
+        cmd.current_dir(cwd);
+    }
+
+    println!("🪝 Running hook: {}", command);
+
+    let child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            return HookOutput {
+                command: command.to_string(),
+                success: false,
+                exit_code: None,
+                timed_out: false,
+                stdout: String::new(),
+                stderr: e.to_string(),
+            }
+        }
+    };
+
+    // 超时后 future 被丢弃，kill_on_drop 会结束子进程
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) => HookOutput {
+            command: command.to_string(),
+            success: output.status.success(),
+            exit_code: output.status.code(),
+            timed_out: false,
+            stdout: capture(&output.stdout),
+            stderr: capture(&output.stderr),
+        },
+        Ok(Err(e)) => HookOutput {
+            command: command.to_string(),
+            success: false,
+            exit_code: None,
+            timed_out: false,
+            stdout: String::new(),
+            stderr: e.to_string(),
+        },
+        Err(_) => HookOutput {
+            command: command.to_string(),
+            success: false,
+            exit_code: None,
+            timed_out: true,
+            stdout: String::new(),
+            stderr: format!("killed after {}s", timeout.as_secs()),
+        },
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+// 只保留输出的末尾部分（错误信息通常在最后）
+fn capture(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    if text.len() <= MAX_CAPTURED_OUTPUT {
+        return text.into_owned();
+    }
+
+    let mut start = text.len() - MAX_CAPTURED_OUTPUT;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("…{}", &text[start..])
+}
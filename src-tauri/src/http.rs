@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+const SETTINGS_FILE: &str = "http_settings.json";
+
+// 全局共享的 HTTP 客户端：下载引擎和接口调用共用同一个连接池，
+// 避免每个文件都重新建立连接和 TLS 握手
+static CLIENT: once_cell::sync::Lazy<RwLock<(HttpSettings, reqwest::Client)>> =
+    once_cell::sync::Lazy::new(|| {
+        let settings = HttpSettings::default();
+        let client = build_client(&settings).expect("failed to build default HTTP client");
+        RwLock::new((settings, client))
+    });
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct HttpSettings {
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    pub timeout_secs: u64,
+    pub tcp_keepalive_secs: u64,
+    pub http2: bool, // 服务器支持时通过 ALPN 使用 HTTP/2
+    pub proxy: Option<ProxySettings>,
+    pub ca_certificates: Vec<String>, // 额外信任的根证书（PEM 文件路径，可含多个证书）
+}
+
+// 代理设置，支持 http://、https://、socks5:// 和 socks5h://
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct ProxySettings {
+    pub url: String,
+    pub no_proxy: Option<String>, // 逗号分隔的主机、域名或 CIDR，如 "localhost,.edu.cn,10.0.0.0/8"
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: 20,
+            pool_idle_timeout_secs: 90,
+            connect_timeout_secs: 10,
+            timeout_secs: 300,
+            tcp_keepalive_secs: 60,
+            http2: true,
+            proxy: None,
+            ca_certificates: Vec::new(),
+        }
+    }
+}
+
+impl HttpSettings {
+    // 返回给前端的设置不含代理密码
+    pub fn redacted(mut self) -> Self {
+        if let Some(ref mut proxy) = self.proxy {
+            proxy.password = None;
+        }
+        self
+    }
+
+    // 前端回传的设置没有密码时，代理地址和用户名未变则沿用当前密码；清除密码需传空字符串
+    pub fn keep_password(mut self, current: &HttpSettings) -> Self {
+        if let (Some(proxy), Some(current)) = (self.proxy.as_mut(), current.proxy.as_ref()) {
+            if proxy.password.is_none()
+                && proxy.url == current.url
+                && proxy.username == current.username
+            {
+                proxy.password = current.password.clone();
+            }
+        }
+        self
+    }
+}
+
+// 连接复用统计：请求数减去新建连接数即为复用连接的请求数
+#[derive(Clone, Serialize, Debug)]
+pub struct HttpStats {
+    pub requests: u64,
+    pub connections: u64,
+    pub reused: u64,
+    pub reuse_ratio: f64,
+}
+
+// reqwest::Client 内部是 Arc，克隆开销很小
+pub fn client() -> reqwest::Client {
+    CLIENT.read().unwrap().1.clone()
+}
+
+pub fn settings() -> HttpSettings {
+    CLIENT.read().unwrap().0.clone()
+}
+
+// 按新设置重建客户端；进行中的请求继续使用旧客户端直到结束
+pub fn configure(settings: HttpSettings) -> Result<(), String> {
+    let client = build_client(&settings)?;
+    *CLIENT.write().unwrap() = (settings, client);
+    Ok(())
+}
+
+// 发送请求并计数，用于统计连接复用
+pub async fn send(request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    request.send().await
+}
+
+pub fn stats() -> HttpStats {
+    let requests = REQUESTS.load(Ordering::Relaxed);
+    let connections = CONNECTIONS.load(Ordering::Relaxed);
+    let reused = requests.saturating_sub(connections);
+    HttpStats {
+        requests,
+        connections,
+        reused,
+        reuse_ratio: if requests == 0 {
+            0.0
+        } else {
+            reused as f64 / requests as f64
+        },
+    }
+}
+
+pub fn load_settings(config_dir: &Path) -> HttpSettings {
+    std::fs::read_to_string(config_dir.join(SETTINGS_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(config_dir: &Path, settings: &HttpSettings) -> Result<(), String> {
+    std::fs::create_dir_all(config_dir).map_err(|e| e.to_string())?;
+    let text = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(config_dir.join(SETTINGS_FILE), text)
+        .map_err(|e| format!("Failed to save HTTP settings: {}", e))
+}
+
+fn build_client(settings: &HttpSettings) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent("MatchDownload/1.0")
+        .pool_max_idle_per_host(settings.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(settings.pool_idle_timeout_secs))
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+        .timeout(Duration::from_secs(settings.timeout_secs))
+        .tcp_keepalive(Duration::from_secs(settings.tcp_keepalive_secs))
+        .connector_layer(CountConnections);
+    if !settings.http2 {
+        builder = builder.http1_only();
+    }
+    if let Some(proxy) = settings.proxy.as_ref().filter(|p| !p.url.trim().is_empty()) {
+        builder = builder.proxy(build_proxy(proxy)?);
+    }
+    for path in &settings.ca_certificates {
+        for certificate in load_certificates(Path::new(path))? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+// reqwest 也接受 socks4，但 socks4 不支持认证，设置用户名时 basic_auth 会 panic
+const PROXY_SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+
+fn build_proxy(settings: &ProxySettings) -> Result<reqwest::Proxy, String> {
+    let url = settings.url.trim();
+    // 没有协议时 reqwest 按 http 处理
+    let scheme = url
+        .split_once("://")
+        .map_or("http".to_string(), |(scheme, _)| {
+            scheme.to_ascii_lowercase()
+        });
+    if !PROXY_SCHEMES.contains(&scheme.as_str()) {
+        return Err(format!(
+            "Unsupported proxy scheme {:?} in {}, expected one of {}",
+            scheme,
+            settings.url,
+            PROXY_SCHEMES.join(", ")
+        ));
+    }
+    let mut proxy = reqwest::Proxy::all(url)
+        .map_err(|e| format!("Invalid proxy URL {}: {}", settings.url, e))?;
+    if let Some(username) = settings.username.as_ref().filter(|u| !u.is_empty()) {
+        proxy = proxy.basic_auth(username, settings.password.as_deref().unwrap_or(""));
+    }
+    if let Some(ref no_proxy) = settings.no_proxy {
+        proxy = proxy.no_proxy(reqwest::NoProxy::from_string(no_proxy));
+    }
+    Ok(proxy)
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<reqwest::Certificate>, String> {
+    let pem = std::fs::read(path)
+        .map_err(|e| format!("Failed to read certificate {}: {}", path.display(), e))?;
+    let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+        .map_err(|e| format!("Invalid certificate {}: {}", path.display(), e))?;
+    if certificates.is_empty() {
+        return Err(format!("No certificate found in {}", path.display()));
+    }
+    Ok(certificates)
+}
+
+// 连接器每被调用一次就是新建一个连接（连接池命中时不会调用）
+#[derive(Clone)]
+struct CountConnections;
+
+impl<S> tower::Layer<S> for CountConnections {
+    type Service = CountConnectionsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CountConnectionsService { inner }
+    }
+}
+
+#[derive(Clone)]
+struct CountConnectionsService<S> {
+    inner: S,
+}
+
+impl<S, R> tower::Service<R> for CountConnectionsService<S>
+where
+    S: tower::Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(url: &str) -> ProxySettings {
+        ProxySettings {
+            url: url.to_string(),
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn accepts_supported_proxy_schemes() {
+        for url in [
+            "http://127.0.0.1:8080",
+            "HTTPS://proxy.example.com",
+            "socks5://127.0.0.1:1080",
+            "socks5h://127.0.0.1:1080",
+            "127.0.0.1:8080",
+        ] {
+            assert!(
+                build_proxy(&proxy(url)).is_ok(),
+                "{} should be accepted",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_proxy_schemes() {
+        for url in [
+            "socks4://127.0.0.1:1080",
+            "socks4a://127.0.0.1:1080",
+            "ftp://proxy",
+        ] {
+            assert!(
+                build_proxy(&proxy(url)).is_err(),
+                "{} should be rejected",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn proxy_password_is_redacted_and_kept() {
+        let current = HttpSettings {
+            proxy: Some(proxy("http://127.0.0.1:8080")),
+            ..Default::default()
+        };
+        let shown = current.clone().redacted();
+        assert_eq!(shown.proxy.as_ref().unwrap().password, None);
+
+        let saved = shown.clone().keep_password(&current);
+        assert_eq!(saved.proxy.unwrap().password.as_deref(), Some("secret"));
+
+        let mut moved = shown.clone();
+        moved.proxy.as_mut().unwrap().url = "http://10.0.0.1:8080".to_string();
+        assert_eq!(moved.keep_password(&current).proxy.unwrap().password, None);
+
+        let mut cleared = shown;
+        cleared.proxy.as_mut().unwrap().password = Some(String::new());
+        let cleared = cleared.keep_password(&current);
+        assert_eq!(cleared.proxy.unwrap().password.as_deref(), Some(""));
+    }
+}
@@ -0,0 +1,494 @@
+use crate::commands::{Work, WorkFile};
+use crate::sanitize;
+use pinyin::ToPinyin;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+// 与原有固定结构一致：比赛名称/赛段名称/学院/专业/班级/学生姓名_学号
+pub const DEFAULT_LAYOUT: &str =
+    "{match_title}/{stage_name}/{college_name}/{major_name}/{class_name}/{student_name}_{student_id}";
+
+const PRESETS_FILE: &str = "layout_presets.json";
+
+// 目录模板可用的作品字段
+pub const WORK_FIELDS: &[&str] = &[
+    "id",
+    "title",
+    "student_id",
+    "student_name",
+    "college_name",
+    "major_name",
+    "class_name",
+    "match_title",
+    "stage_name",
+    "check_status",
+    "createtime",
+    "create_date",
+];
+
+// 文件名模板可用的字段：作品字段 + 文件字段
+pub const FILE_FIELDS: &[&str] = &[
+    "id",
+    "title",
+    "student_id",
+    "student_name",
+    "college_name",
+    "major_name",
+    "class_name",
+    "match_title",
+    "stage_name",
+    "check_status",
+    "createtime",
+    "create_date",
+    "file_id",
+    "element_label",
+    "element_type",
+    "name",          // 原文件名（不含扩展名）
+    "original_name", // 原文件名（含扩展名）
+    "ext",
+    "index", // 作品内的文件序号，从 1 开始
+    "seq",   // 批次内的文件序号，按总数补零
+];
+
+// 转写为拼音的字段，供不支持中文路径的 FTP、评审机和剪辑软件使用
+pub const PINYIN_FIELDS: &[&str] = &["college_name", "major_name", "class_name", "student_name"];
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PinyinStyle {
+    #[default]
+    Off,
+    Plain, // 计算机学院 -> JiSuanJiXueYuan
+    Tone,  // 计算机学院 -> JìSuànJīXuéYuàn
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Field {
+        name: String,
+        default: Option<String>,
+    },
+}
+
+// 解析后的路径模板，按 '/' 分段，字段值不会产生新的目录层级
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Vec<Vec<Token>>,
+}
+
+impl Template {
+    // 语法：{field} 或 {field|缺省值}，'/' 分隔目录
+    pub fn parse(template: &str, fields: &[&str]) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.trim().chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    let mut inner = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        inner.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("Unclosed '{{' in template: {}", template));
+                    }
+
+                    let (name, default) = match inner.split_once('|') {
+                        Some((name, default)) => (name.trim(), Some(default.to_string())),
+                        None => (inner.trim(), None),
+                    };
+                    if !fields.contains(&name) {
+                        return Err(format!(
+                            "Unknown field {{{}}}, available: {}",
+                            name,
+                            fields.join(", ")
+                        ));
+                    }
+                    tokens.push(Token::Field {
+                        name: name.to_string(),
+                        default,
+                    });
+                }
+                '}' => return Err(format!("Unexpected '}}' in template: {}", template)),
+                '/' | '\\' => {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    if tokens.is_empty() {
+                        return Err(format!("Empty folder segment in template: {}", template));
+                    }
+                    segments.push(std::mem::take(&mut tokens));
+                }
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        if tokens.is_empty() {
+            if segments.is_empty() {
+                return Err("Template is empty".to_string());
+            }
+            return Err(format!("Template ends with a separator: {}", template));
+        }
+        segments.push(tokens);
+
+        Ok(Self { segments })
+    }
+
+    // 文件名模板只能有一段
+    pub fn parse_file_name(template: &str) -> Result<Self, String> {
+        let parsed = Self::parse(template, FILE_FIELDS)?;
+        if parsed.segments.len() > 1 {
+            return Err(format!(
+                "File name template cannot contain '/': {}",
+                template
+            ));
+        }
+        Ok(parsed)
+    }
+
+    pub fn uses_field(&self, field: &str) -> bool {
+        self.segments.iter().flatten().any(|token| match token {
+            Token::Field { name, .. } => name == field,
+            Token::Literal(_) => false,
+        })
+    }
+
+    // 渲染每一段，lookup 返回 None 或空字符串时依次使用模板缺省值、内置缺省值；
+    // PINYIN_FIELDS 中的字段（含缺省值）按 pinyin 转写
+    pub fn render(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<String>,
+        pinyin: PinyinStyle,
+    ) -> Vec<String> {
+        self.segments
+            .iter()
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .map(|token| match token {
+                        Token::Literal(text) => text.clone(),
+                        Token::Field { name, default } => {
+                            let value = lookup(name)
+                                .filter(|v| !v.trim().is_empty())
+                                .or_else(|| default.clone())
+                                .or_else(|| builtin_default(name).map(str::to_string))
+                                .unwrap_or_default();
+                            if PINYIN_FIELDS.contains(&name.as_str()) {
+                                to_pinyin(&value, pinyin)
+                            } else {
+                                value
+                            }
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// 作品字段取值，缺失时返回 None
+pub fn work_field(work: &Work, name: &str) -> Option<String> {
+    match name {
+        "id" => Some(work.id.to_string()),
+        "title" => Some(work.title.clone()),
+        "student_id" => Some(work.student_id.to_string()),
+        "student_name" => work.student_name.clone(),
+        "college_name" => work.college_name.clone(),
+        "major_name" => work.major_name.clone(),
+        "class_name" => work.class_name.clone(),
+        "match_title" => work.match_title.clone(),
+        "stage_name" => work.stage_name.clone(),
+        "check_status" => Some(work.check_status.to_string()),
+        "createtime" => Some(work.createtime.to_string()),
+        "create_date" => chrono::DateTime::from_timestamp(work.createtime, 0).map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d")
+                .to_string()
+        }),
+        _ => None,
+    }
+}
+
+// 汉字转为首字母大写的拼音音节，其他字符保持不变
+pub fn to_pinyin(text: &str, style: PinyinStyle) -> String {
+    if style == PinyinStyle::Off {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len() * 2);
+    for c in text.chars() {
+        match c.to_pinyin() {
+            Some(pinyin) => {
+                let syllable = match style {
+                    PinyinStyle::Tone => pinyin.with_tone(),
+                    _ => pinyin.plain(),
+                };
+                let mut chars = syllable.chars();
+                if let Some(first) = chars.next() {
+                    result.extend(first.to_uppercase());
+                    result.push_str(chars.as_str());
+                }
+            }
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+// 文件在批次中的位置，用于 {index} 和 {seq}
+pub struct FilePosition {
+    pub index: usize,
+    pub seq: usize,
+    pub seq_width: usize,
+}
+
+// 按文件名模板生成文件名；模板未使用 {ext} 时自动保留原扩展名
+pub fn render_file_name(
+    template: &Template,
+    work: &Work,
+    file: &WorkFile,
+    position: &FilePosition,
+    pinyin: PinyinStyle,
+) -> String {
+    let original = file.user_content.name.as_str();
+    let (stem, ext) = split_extension(original);
+
+    let lookup = |name: &str| match name {
+        "file_id" => Some(file.id.to_string()),
+        "element_label" => Some(file.element_label.clone()),
+        "element_type" => Some(file.element_type.to_string()),
+        "name" => Some(stem.to_string()),
+        "original_name" => Some(original.to_string()),
+        "ext" => ext.map(str::to_string),
+        "index" => Some(position.index.to_string()),
+        "seq" => Some(format!(
+            "{:0width$}",
+            position.seq,
+            width = position.seq_width
+        )),
+        _ => work_field(work, name),
+    };
+
+    let mut name = template.render(&lookup, pinyin).concat();
+    if !template.uses_field("ext") {
+        if let Some(ext) = ext {
+            name.push('.');
+            name.push_str(ext);
+        }
+    }
+    name.trim_end_matches('.').to_string()
+}
+
+// 拆分扩展名："a.tar.mp4" -> ("a.tar", Some("mp4"))，".bashrc" 视为无扩展名
+pub fn split_extension(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty()
+                && !ext.is_empty()
+                && ext.len() <= 10
+                && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            (stem, Some(ext))
+        }
+        _ => (name, None),
+    }
+}
+
+// 同一目录下文件名去重（忽略大小写，兼容 Windows/macOS）："a.mp4" -> "a (2).mp4"；
+// 加上序号后超过长度上限时截短主名，扩展名和序号保留
+pub fn dedupe_name(name: &str, used: &mut HashSet<String>) -> String {
+    if used.insert(name.to_lowercase()) {
+        return name.to_string();
+    }
+
+    let (stem, ext) = split_extension(name);
+    let mut n = 2;
+    loop {
+        let suffix = format!(" ({})", n);
+        let ext_len = ext.map_or(0, |ext| ext.len() + 1);
+        let stem = sanitize::truncate_bytes(
+            stem,
+            sanitize::MAX_NAME_BYTES.saturating_sub(suffix.len() + ext_len),
+        );
+        let candidate = match ext {
+            Some(ext) => format!("{}{}.{}", stem, suffix, ext),
+            None => format!("{}{}", stem, suffix),
+        };
+        if used.insert(candidate.to_lowercase()) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+// 与原有目录结构一致的缺省名称
+fn builtin_default(name: &str) -> Option<&'static str> {
+    match name {
+        "student_name" => Some("未知学生"),
+        "college_name" => Some("未知学院"),
+        "major_name" => Some("未知专业"),
+        "class_name" => Some("未知班级"),
+        "match_title" => Some("未命名比赛"),
+        "stage_name" => Some("未命名赛段"),
+        "title" => Some("未命名作品"),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct LayoutPreset {
+    pub name: String,
+    pub template: String,
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+pub fn builtin_presets() -> Vec<LayoutPreset> {
+    [
+        ("默认（比赛/赛段/学院/专业/班级/学生）", DEFAULT_LAYOUT),
+        (
+            "按学院",
+            "{stage_name}/{college_name}/{student_name}_{student_id}",
+        ),
+        ("平铺（作品ID_标题）", "{id}_{title}"),
+    ]
+    .into_iter()
+    .map(|(name, template)| LayoutPreset {
+        name: name.to_string(),
+        template: template.to_string(),
+        builtin: true,
+    })
+    .collect()
+}
+
+// 用户保存的预设，存放在应用配置目录
+pub fn load_presets(config_dir: &Path) -> Vec<LayoutPreset> {
+    std::fs::read_to_string(config_dir.join(PRESETS_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_presets(config_dir: &Path, presets: &[LayoutPreset]) -> Result<(), String> {
+    std::fs::create_dir_all(config_dir).map_err(|e| e.to_string())?;
+    let text = serde_json::to_string_pretty(presets).map_err(|e| e.to_string())?;
+    std::fs::write(config_dir.join(PRESETS_FILE), text)
+        .map_err(|e| format!("Failed to save layout presets: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Vec<String> {
+        Template::parse(template, WORK_FIELDS)
+            .unwrap()
+            .render(lookup, PinyinStyle::Off)
+    }
+
+    #[test]
+    fn parses_segments_and_fields() {
+        let values = |name: &str| match name {
+            "college_name" => Some("计算机学院".to_string()),
+            "student_id" => Some("42".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            render("{college_name}\\{student_name}_{student_id}", &values),
+            vec!["计算机学院", "未知学生_42"]
+        );
+        assert_eq!(
+            render("作品/{class_name|无班级}-{title| }", &values),
+            vec!["作品", "无班级- "]
+        );
+        assert!(Template::parse(DEFAULT_LAYOUT, WORK_FIELDS).is_ok());
+    }
+
+    #[test]
+    fn empty_values_fall_back_to_defaults() {
+        let values = |_: &str| Some("  ".to_string());
+        assert_eq!(render("{major_name|专业}", &values), vec!["专业"]);
+        assert_eq!(render("{id}", &values), vec![""]);
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for template in [
+            "",
+            "   ",
+            "{college_name",
+            "college_name}",
+            "{unknown}",
+            "a//b",
+            "/a",
+            "a/",
+        ] {
+            assert!(
+                Template::parse(template, WORK_FIELDS).is_err(),
+                "{:?} should be rejected",
+                template
+            );
+        }
+        // 文件字段不能用在目录模板中
+        assert!(Template::parse("{ext}", WORK_FIELDS).is_err());
+    }
+
+    #[test]
+    fn file_name_template_is_single_segment() {
+        let template = Template::parse_file_name("{seq}_{name}.{ext}").unwrap();
+        assert!(template.uses_field("ext"));
+        assert!(!template.uses_field("title"));
+        assert!(Template::parse_file_name("{title}/{name}").is_err());
+    }
+
+    #[test]
+    fn splits_extension() {
+        assert_eq!(split_extension("a.tar.mp4"), ("a.tar", Some("mp4")));
+        assert_eq!(split_extension(".bashrc"), (".bashrc", None));
+        assert_eq!(
+            split_extension("report.final version"),
+            ("report.final version", None)
+        );
+        assert_eq!(split_extension("noext"), ("noext", None));
+    }
+
+    #[test]
+    fn dedupes_case_insensitively() {
+        let mut used = HashSet::new();
+        assert_eq!(dedupe_name("a.mp4", &mut used), "a.mp4");
+        assert_eq!(dedupe_name("A.MP4", &mut used), "A (2).MP4");
+        assert_eq!(dedupe_name("a.mp4", &mut used), "a (3).mp4");
+        assert_eq!(dedupe_name("readme", &mut used), "readme");
+        assert_eq!(dedupe_name("readme", &mut used), "readme (2)");
+    }
+
+    #[test]
+    fn deduped_names_stay_within_max_bytes() {
+        let name = sanitize::sanitize_file_name(
+            &format!("{}.mp4", "作".repeat(100)),
+            sanitize::FsProfile::Posix,
+        );
+        let mut used = HashSet::new();
+        dedupe_name(&name, &mut used);
+        for _ in 0..10 {
+            let deduped = dedupe_name(&name, &mut used);
+            assert!(deduped.len() <= sanitize::MAX_NAME_BYTES, "{}", deduped);
+            assert!(deduped.ends_with(").mp4"));
+        }
+    }
+}
@@ -1,6 +1,21 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+pub mod api;
+pub mod archive;
 pub mod commands;
+pub mod credentials;
 pub mod downloader;
+pub mod error;
+pub mod filter;
+pub mod hooks;
+pub mod http;
+pub mod layout;
+pub mod manifest;
+pub mod oauth;
+pub mod paths;
+pub mod sanitize;
+pub mod session;
+pub mod sync;
+pub mod xlsx;
 
 use downloader::DownloadManager;
 use std::sync::Arc;
@@ -19,6 +34,15 @@ pub fn run() {
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .setup(|app| {
+            if let Ok(config_dir) = app.path().app_config_dir() {
+                if let Err(e) = http::configure(http::load_settings(&config_dir)) {
+                    eprintln!("⚠️ {}", e);
+                }
+                if let Err(e) = api::configure(api::load_settings(&config_dir)) {
+                    eprintln!("⚠️ {}", e);
+                }
+            }
+
             let win = app.get_webview_window("main").unwrap();
             if let Some(monitor) = win.current_monitor().unwrap() {
                 let size = monitor.size();
@@ -34,19 +58,39 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::get_schools,
             commands::start_oauth,
+            commands::cancel_oauth,
             commands::exchange_token,
             commands::get_user_info,
+            commands::logout,
+            commands::save_session,
+            commands::restore_session,
             commands::fetch_matches,
             commands::fetch_stages,
             commands::fetch_works,
+            commands::filter_works,
             commands::download_works,
+            commands::plan_download,
+            commands::start_download_plan,
+            commands::sync_works,
             commands::get_system_info,
+            commands::get_http_settings,
+            commands::set_http_settings,
+            commands::get_http_stats,
+            commands::get_api_settings,
+            commands::set_api_settings,
             commands::pause_downloads,
             commands::resume_downloads,
             commands::stop_downloads,
             commands::stop_batch,
             commands::pause_batch,
             commands::resume_batch,
+            commands::get_batch_report,
+            commands::export_batch_archive,
+            commands::export_works_xlsx,
+            commands::preview_layout,
+            commands::list_layout_presets,
+            commands::save_layout_preset,
+            commands::delete_layout_preset,
             commands::get_download_state,
             commands::get_current_concurrency,
             commands::open_folder
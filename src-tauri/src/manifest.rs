@@ -0,0 +1,214 @@
+use crate::downloader::ItemMeta;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const MANIFEST_CSV: &str = "manifest.csv";
+pub const MANIFEST_JSON: &str = "manifest.json";
+
+pub const STATUS_COMPLETED: &str = "completed";
+pub const STATUS_ERROR: &str = "error";
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_REJECTED: &str = "rejected"; // 路径不安全，未下载
+pub const STATUS_SKIPPED: &str = "skipped"; // 被文件规则跳过
+
+// 同一根目录的清单读取、合并和写入必须串行，否则并发结束的批次会互相覆盖
+static ROOT_LOCKS: once_cell::sync::Lazy<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 清单中的一行，对应一个文件；batch_id 为最后写入该行的批次
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ManifestRow {
+    #[serde(default)]
+    pub batch_id: String,
+    pub work_id: i32,
+    pub work_title: String,
+    pub check_status: i32,
+    pub createtime: i64,
+    pub student_id: i32,
+    pub student_name: String,
+    pub match_title: String,
+    pub stage_name: String,
+    pub college_name: String,
+    pub major_name: String,
+    pub class_name: String,
+    pub file_id: i32,
+    pub element_label: String,
+    pub element_type: i32,
+    pub original_filename: String,
+    pub final_filename: String,
+    pub relative_path: String,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub status: String,        // STATUS_* 之一
+    pub error: Option<String>, // 失败原因，或拒绝/跳过的原因
+}
+
+impl ManifestRow {
+    // 只含作品信息的行，文件名、路径和摘要由调用方补充
+    pub fn new(batch_id: &str, meta: ItemMeta, status: &str) -> Self {
+        Self {
+            batch_id: batch_id.to_string(),
+            work_id: meta.work_id,
+            work_title: meta.work_title,
+            check_status: meta.check_status,
+            createtime: meta.createtime,
+            student_id: meta.student_id,
+            student_name: meta.student_name.unwrap_or_default(),
+            match_title: meta.match_title.unwrap_or_default(),
+            stage_name: meta.stage_name.unwrap_or_default(),
+            college_name: meta.college_name.unwrap_or_default(),
+            major_name: meta.major_name.unwrap_or_default(),
+            class_name: meta.class_name.unwrap_or_default(),
+            file_id: meta.file_id,
+            element_label: meta.element_label,
+            element_type: meta.element_type,
+            original_filename: meta.original_name,
+            final_filename: String::new(),
+            relative_path: String::new(),
+            size: None,
+            sha256: None,
+            status: status.to_string(),
+            error: None,
+        }
+    }
+
+    fn is_excluded(&self) -> bool {
+        self.status == STATUS_REJECTED || self.status == STATUS_SKIPPED
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestJson {
+    batch_id: String, // 最近一次写入的批次
+    generated_at: String,
+    files: Vec<ManifestRow>,
+}
+
+// 在批次根目录写入 manifest.csv 和 manifest.json。
+// 同一目录下已有的清单会合并：同一文件以本批次的记录为准，
+// 但本批次跳过或拒绝的文件不会覆盖之前已完成的记录（文件仍在磁盘上）
+pub fn write_manifest(root: &Path, batch_id: &str, rows: Vec<ManifestRow>) -> io::Result<()> {
+    std::fs::create_dir_all(root)?;
+    let lock = root_lock(root);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    let rows = merge_rows(read_rows(root)?, rows);
+
+    // 先写临时文件再替换，读到的清单总是完整的
+    // CSV 带 BOM，Excel 直接打开中文不乱码
+    write_replacing(&root.join(MANIFEST_CSV), |file| {
+        file.write_all(b"\xEF\xBB\xBF")?;
+        let mut writer = csv::Writer::from_writer(file);
+        for row in &rows {
+            writer.serialize(row).map_err(io::Error::other)?;
+        }
+        writer.flush()
+    })?;
+
+    let json = ManifestJson {
+        batch_id: batch_id.to_string(),
+        generated_at: chrono::Local::now().to_rfc3339(),
+        files: rows,
+    };
+    write_replacing(&root.join(MANIFEST_JSON), |file| {
+        serde_json::to_writer_pretty(&mut *file, &json).map_err(io::Error::other)?;
+        file.flush()
+    })
+}
+
+fn root_lock(root: &Path) -> Arc<Mutex<()>> {
+    let key = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let mut locks = ROOT_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks.entry(key).or_default().clone()
+}
+
+fn write_replacing(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+    let mut file = BufWriter::new(File::create(&temp)?);
+    write(&mut file)?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    std::fs::rename(&temp, path)
+}
+
+// 已有清单中的行；没有清单时从空清单开始。无法解析的清单改名为 manifest.json.bak
+// 保留下来再从空清单开始，不会被直接覆盖
+fn read_rows(root: &Path) -> io::Result<Vec<ManifestRow>> {
+    let path = root.join(MANIFEST_JSON);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    match serde_json::from_str::<ManifestJson>(&text) {
+        Ok(json) => Ok(json.files),
+        Err(e) => {
+            eprintln!("⚠️ Unreadable {}: {}", path.display(), e);
+            std::fs::rename(&path, root.join(format!("{}.bak", MANIFEST_JSON)))?;
+            Ok(Vec::new())
+        }
+    }
+}
+
+// 清单中某批次已完成文件的路径（相对于批次根目录，'/' 分隔），批次不在内存中时导出用
+pub fn completed_files(root: &Path, batch_id: &str) -> io::Result<Vec<String>> {
+    let text = match std::fs::read_to_string(root.join(MANIFEST_JSON)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let json: ManifestJson = serde_json::from_str(&text).map_err(io::Error::other)?;
+    Ok(json
+        .files
+        .into_iter()
+        .filter(|row| row.batch_id == batch_id && row.status == STATUS_COMPLETED)
+        .map(|row| row.relative_path)
+        .collect())
+}
+
+fn merge_rows(mut existing: Vec<ManifestRow>, rows: Vec<ManifestRow>) -> Vec<ManifestRow> {
+    let mut index: HashMap<(i32, i32), usize> = existing
+        .iter()
+        .enumerate()
+        .map(|(i, row)| ((row.work_id, row.file_id), i))
+        .collect();
+
+    for row in rows {
+        match index.get(&(row.work_id, row.file_id)) {
+            Some(&i) if row.is_excluded() && existing[i].status == STATUS_COMPLETED => {}
+            Some(&i) => existing[i] = row,
+            None => {
+                index.insert((row.work_id, row.file_id), existing.len());
+                existing.push(row);
+            }
+        }
+    }
+    existing
+}
+
+// 计算文件 SHA-256，返回 (大小, 十六进制摘要)
+pub fn hash_file(path: &Path) -> io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    let mut size = 0u64;
+
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+
+    Ok((size, hex::encode(hasher.finalize())))
+}
@@ -0,0 +1,317 @@
+use crate::error::AppError;
+use axum::extract::{Query, State};
+use axum::response::Html;
+use axum::Router;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+type CallbackResult = Result<String, AppError>;
+
+// 进行中的一次授权：回调的 state 必须一致，换取令牌时带上 code_verifier 和 redirect_uri
+#[derive(Clone, Debug)]
+pub struct OauthFlow {
+    pub domain: String,
+    pub state: String,
+    pub code_verifier: String,
+    pub redirect_uri: String,
+}
+
+impl OauthFlow {
+    // redirect_uri 在回调服务绑定端口后填写
+    pub fn new(domain: &str) -> Self {
+        Self {
+            domain: domain.to_string(),
+            state: uuid::Uuid::new_v4().to_string(),
+            code_verifier: new_code_verifier(),
+            redirect_uri: String::new(),
+        }
+    }
+
+    pub fn code_challenge(&self) -> String {
+        code_challenge(&self.code_verifier)
+    }
+}
+
+// PKCE code_verifier：64 个十六进制字符（RFC 7636 要求 43~128 个非保留字符）
+fn new_code_verifier() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+// S256：BASE64URL(SHA256(code_verifier))，不带填充
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    error: Option<String>,
+    state: Option<String>,
+}
+
+struct CallbackContext {
+    state: String,
+    result: Mutex<Option<oneshot::Sender<CallbackResult>>>,
+}
+
+// 单次授权使用的本地回调服务，收到回调、超时或被丢弃时关闭
+pub struct CallbackServer {
+    port: u16,
+    result: Option<oneshot::Receiver<CallbackResult>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl CallbackServer {
+    // ports 为空时使用系统分配的空闲端口，否则依次尝试（需与平台登记的回调地址一致）
+    pub async fn bind(ports: &[u16], state: &str) -> Result<Self, AppError> {
+        let listener = bind_loopback(ports).await?;
+        let port = listener.local_addr()?.port();
+        let (result_tx, result_rx) = oneshot::channel();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let context = Arc::new(CallbackContext {
+            state: state.to_string(),
+            result: Mutex::new(Some(result_tx)),
+        });
+        let router = Router::new()
+            .route("/callback", axum::routing::get(handle_callback))
+            .with_state(context);
+
+        let task = tokio::spawn(async move {
+            let shutdown = async {
+                let _ = shutdown_rx.await;
+            };
+            if let Err(e) = axum::serve(listener, router)
+                .with_graceful_shutdown(shutdown)
+                .await
+            {
+                eprintln!("Callback server error: {}", e);
+            }
+            println!("🔌 OAuth callback server on port {} stopped", port);
+        });
+        println!("🔌 OAuth callback server listening on port {}", port);
+
+        Ok(Self {
+            port,
+            result: Some(result_rx),
+            shutdown: Some(shutdown_tx),
+            task: Some(task),
+        })
+    }
+
+    pub fn redirect_uri(&self) -> String {
+        format!("http://localhost:{}/callback", self.port)
+    }
+
+    // 等待回调返回授权码，cancel 收到信号（或发送端被丢弃）时返回 Cancelled；
+    // 无论结果如何，结束后关闭服务
+    pub async fn wait(
+        mut self,
+        timeout: Duration,
+        cancel: oneshot::Receiver<()>,
+    ) -> CallbackResult {
+        let Some(result) = self.result.take() else {
+            return Err(AppError::Internal(
+                "OAuth callback already received".to_string(),
+            ));
+        };
+        let outcome = tokio::select! {
+            result = tokio::time::timeout(timeout, result) => match result {
+                Ok(Ok(result)) => result,
+                Ok(Err(_)) => Err(AppError::Internal(
+                    "OAuth callback server stopped".to_string(),
+                )),
+                Err(_) => Err(AppError::Timeout(format!(
+                    "OAuth timeout after {} seconds",
+                    timeout.as_secs()
+                ))),
+            },
+            _ = cancel => Err(AppError::Cancelled("登录已取消".to_string())),
+        };
+        self.shutdown().await;
+        outcome
+    }
+
+    // 停止接受新连接，等回调页面发送完；浏览器保持连接不放时最多等 2 秒
+    async fn shutdown(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(task) = self.task.take() {
+            let abort = task.abort_handle();
+            if tokio::time::timeout(Duration::from_secs(2), task)
+                .await
+                .is_err()
+            {
+                abort.abort();
+            }
+        }
+    }
+}
+
+impl Drop for CallbackServer {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+async fn bind_loopback(ports: &[u16]) -> Result<tokio::net::TcpListener, AppError> {
+    let mut last_error = None;
+    for &port in ports {
+        match tokio::net::TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) => {
+                eprintln!("Failed to bind to port {}: {}", port, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    // 没有配置或都被占用时退回空闲端口，平台不接受该回调地址时会在授权页提示
+    if let Some(e) = last_error {
+        eprintln!(
+            "⚠️ No OAuth callback port available ({:?}): {}, using an ephemeral port",
+            ports, e
+        );
+    }
+    Ok(tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?)
+}
+
+async fn handle_callback(
+    State(context): State<Arc<CallbackContext>>,
+    Query(params): Query<CallbackParams>,
+) -> Html<String> {
+    println!("=== Callback received! ===");
+    // 授权码和 state 不写入日志，只记录是否收到
+    println!(
+        "Code present: {}, error: {:?}",
+        params.code.is_some(),
+        params.error
+    );
+
+    // state 不一致的回调可能来自其他程序或网页，拒绝并继续等待真正的回调
+    if params.state.as_deref() != Some(context.state.as_str()) {
+        eprintln!("❌ OAuth callback rejected: state mismatch");
+        return Html(error_page("授权请求校验失败，请回到应用重新登录"));
+    }
+
+    let result = match (params.code, params.error) {
+        (Some(code), _) => Ok(code),
+        (None, error) => Err(AppError::Unauthorized(format!(
+            "OAuth error: {}",
+            error.unwrap_or_else(|| "missing code".to_string())
+        ))),
+    };
+    let success = result.is_ok();
+    let Some(sender) = context.result.lock().unwrap().take() else {
+        return Html(error_page("本次授权已经完成，您可以关闭此页面"));
+    };
+    let _ = sender.send(result);
+
+    if success {
+        Html(SUCCESS_PAGE.to_string())
+    } else {
+        Html(error_page("您可以关闭此页面"))
+    }
+}
+
+const SUCCESS_PAGE: &str = r#"
+    <!DOCTYPE html>
+    <html>
+    <head>
+        <title>授权成功</title>
+        <style>
+            body { font-family: -apple-system, sans-serif; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); }
+            .card { background: white; padding: 40px; border-radius: 12px; text-align: center; box-shadow: 0 20px 60px rgba(0,0,0,0.3); }
+            h1 { color: #48bb78; margin: 0 0 16px 0; }
+            p { color: #4a5568; }
+        </style>
+        <script>
+            // 自动关闭标签页
+            setTimeout(function() {
+                window.close();
+            }, 1500);
+        </script>
+    </head>
+    <body>
+        <div class="card">
+            <h1>✓ 授权成功</h1>
+            <p>页面将自动关闭...</p>
+        </div>
+    </body>
+    </html>
+    "#;
+
+fn error_page(message: &str) -> String {
+    format!(
+        r#"
+        <!DOCTYPE html>
+        <html>
+        <head>
+            <title>授权失败</title>
+            <style>
+                body {{ font-family: -apple-system, sans-serif; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); }}
+                .card {{ background: white; padding: 40px; border-radius: 12px; text-align: center; box-shadow: 0 20px 60px rgba(0,0,0,0.3); }}
+                h1 {{ color: #f56565; margin: 0 0 16px 0; }}
+                p {{ color: #4a5568; }}
+            </style>
+        </head>
+        <body>
+            <div class="card">
+                <h1>✗ 授权失败</h1>
+                <p>{}</p>
+            </div>
+        </body>
+        </html>
+        "#,
+        message
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 7636 附录 B 的示例
+    #[test]
+    fn code_challenge_matches_rfc7636_vector() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn code_verifier_is_valid_for_pkce() {
+        let verifier = new_code_verifier();
+        assert!((43..=128).contains(&verifier.len()));
+        assert!(verifier.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(verifier, new_code_verifier());
+    }
+
+    // 取消后回调服务释放端口，下一次登录可以绑定同一个登记端口
+    #[tokio::test]
+    async fn cancelled_server_releases_port() {
+        let first = CallbackServer::bind(&[], "a").await.unwrap();
+        let port = first.port;
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let waiting = tokio::spawn(first.wait(Duration::from_secs(30), cancel_rx));
+        cancel_tx.send(()).unwrap();
+        let result = waiting.await.unwrap();
+        assert!(matches!(result, Err(AppError::Cancelled(_))));
+
+        let second = CallbackServer::bind(&[port], "b").await.unwrap();
+        assert_eq!(second.port, port);
+    }
+}
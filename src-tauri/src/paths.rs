@@ -0,0 +1,183 @@
+use crate::error::AppError;
+use crate::sanitize::{self, UnicodeForm};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+// 将 root 与若干段名称拼接，确保结果仍在 root 之内
+// 每段必须是单个普通名称；已存在的部分会解析符号链接后再比较
+pub fn resolve_within(root: &Path, segments: &[&str]) -> Result<PathBuf, AppError> {
+    let mut path = root.to_path_buf();
+    for segment in segments {
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) if name == *segment => path.push(segment),
+            _ => {
+                return Err(AppError::InvalidPath(format!(
+                    "Unsafe path segment {:?} would leave {}",
+                    segment,
+                    root.display()
+                )))
+            }
+        }
+    }
+
+    let canonical_root = canonicalize_lenient(root)
+        .map_err(|e| AppError::Io(format!("Invalid save path {}: {}", root.display(), e)))?;
+    let canonical_path = canonicalize_lenient(&path)
+        .map_err(|e| AppError::Io(format!("Invalid path {}: {}", path.display(), e)))?;
+
+    if !canonical_path.starts_with(&canonical_root) {
+        return Err(AppError::InvalidPath(format!(
+            "{} resolves outside of save path {}",
+            path.display(),
+            root.display()
+        )));
+    }
+
+    Ok(path)
+}
+
+// 规范化路径：存在的最长前缀用 canonicalize（解析符号链接），其余部分按字面拼接
+fn canonicalize_lenient(path: &Path) -> std::io::Result<PathBuf> {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    let base = loop {
+        match existing.canonicalize() {
+            Ok(canonical) => break canonical,
+            Err(_) => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    rest.push(name.to_os_string());
+                    existing = parent;
+                }
+                _ => break existing.to_path_buf(),
+            },
+        }
+    };
+
+    let mut result = base;
+    for name in rest.into_iter().rev() {
+        result.push(name);
+    }
+    Ok(normalize_lexically(&result))
+}
+
+// 去掉 "." 并按字面处理 ".."（只用于不存在的尾部）
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+// 磁盘上已有的名称，按规范化后的形式索引：目录 -> (比较键 -> 实际名称)
+// 这样以其他 Unicode 形式保存的旧文件夹/文件会被复用，而不是再建一份
+pub struct ExistingNames {
+    form: UnicodeForm,
+    dirs: HashMap<PathBuf, HashMap<String, String>>,
+}
+
+impl ExistingNames {
+    pub fn new(form: UnicodeForm) -> Self {
+        Self {
+            form,
+            dirs: HashMap::new(),
+        }
+    }
+
+    // dir 中已有与 name 规范化后相同的条目时返回磁盘上的名称，否则返回 name
+    pub fn resolve(&mut self, dir: &Path, name: &str) -> String {
+        if dir.join(name).exists() {
+            return name.to_string();
+        }
+        let form = self.form;
+        let entries = self
+            .dirs
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| list_dir(dir, form));
+        entries
+            .get(&sanitize::comparison_key(name, form))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    // 逐段解析 root 下的目录
+    pub fn resolve_segments(&mut self, root: &Path, segments: &[String]) -> Vec<String> {
+        let mut dir = root.to_path_buf();
+        segments
+            .iter()
+            .map(|segment| {
+                let actual = self.resolve(&dir, segment);
+                dir.push(&actual);
+                actual
+            })
+            .collect()
+    }
+}
+
+fn list_dir(dir: &Path, form: UnicodeForm) -> HashMap<String, String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .map(|name| (sanitize::comparison_key(&name, form), name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("paths-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn joins_plain_segments() {
+        let root = temp_root();
+        let path = resolve_within(&root, &["比赛", "学生_1", "作品.mp4"]).unwrap();
+        assert_eq!(path, root.join("比赛").join("学生_1").join("作品.mp4"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rejects_traversal_and_nested_segments() {
+        let root = temp_root();
+        for segment in ["..", ".", "", "a/b", "../x", "/etc"] {
+            let result = resolve_within(&root, &["ok", segment]);
+            assert!(result.is_err(), "{:?} should be rejected", segment);
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_leaving_root() {
+        let root = temp_root();
+        let outside = temp_root();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        assert!(resolve_within(&root, &["link", "file.txt"]).is_err());
+
+        std::os::unix::fs::symlink(root.join("inner"), root.join("inner-link")).unwrap();
+        std::fs::create_dir(root.join("inner")).unwrap();
+        assert!(resolve_within(&root, &["inner-link", "file.txt"]).is_ok());
+
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+    }
+}
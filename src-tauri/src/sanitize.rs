@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+// 单个文件名/目录名的最大字节数（ext4、APFS 为 255 字节；
+// NTFS、FAT32 为 255 个 UTF-16 单元，按字节截断同样满足）
+pub const MAX_NAME_BYTES: usize = 255;
+
+// Windows 保留设备名，带扩展名同样不可用（如 CON.txt）；
+// 上标数字的 COM¹/LPT¹ 等同样被 Windows 视为设备名
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$", "COM0", "COM1", "COM2", "COM3", "COM4",
+    "COM5", "COM6", "COM7", "COM8", "COM9", "COM¹", "COM²", "COM³", "LPT0", "LPT1", "LPT2", "LPT3",
+    "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9", "LPT¹", "LPT²", "LPT³",
+];
+
+// 目标文件系统，决定哪些字符和名称需要替换
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FsProfile {
+    #[default]
+    Windows, // 默认按最严格的规则，下载的目录可以直接拷到 Windows 共享
+    Macos,
+    Posix,
+    Fat32,
+}
+
+impl FsProfile {
+    fn is_windows_like(self) -> bool {
+        matches!(self, FsProfile::Windows | FsProfile::Fat32)
+    }
+
+    fn is_forbidden(self, c: char) -> bool {
+        match self {
+            FsProfile::Windows | FsProfile::Fat32 => {
+                matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+            }
+            FsProfile::Macos => matches!(c, '/' | ':'),
+            FsProfile::Posix => c == '/',
+        }
+    }
+}
+
+// 路径名称的 Unicode 规范化形式。macOS 以分解形式（NFD）保存文件名，
+// 接口返回的中文也可能混用不同形式，统一后同一个学生只会有一个文件夹
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeForm {
+    #[default]
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+    None, // 保持接口返回的原样
+}
+
+pub fn normalize(name: &str, form: UnicodeForm) -> String {
+    match form {
+        UnicodeForm::Nfc => name.nfc().collect(),
+        UnicodeForm::Nfd => name.nfd().collect(),
+        UnicodeForm::Nfkc => name.nfkc().collect(),
+        UnicodeForm::Nfkd => name.nfkd().collect(),
+        UnicodeForm::None => name.to_string(),
+    }
+}
+
+// 比较两个名称是否指向同一文件时使用的形式；不规范化时按 NFC 比较
+pub fn comparison_key(name: &str, form: UnicodeForm) -> String {
+    match form {
+        UnicodeForm::None => normalize(name, UnicodeForm::Nfc),
+        form => normalize(name, form),
+    }
+}
+
+// 清理文件名，截断时保留扩展名
+pub fn sanitize_file_name(name: &str, profile: FsProfile) -> String {
+    sanitize(name, profile, true)
+}
+
+// 清理目录名，目录名中的 '.' 不视为扩展名
+pub fn sanitize_dir_name(name: &str, profile: FsProfile) -> String {
+    sanitize(name, profile, false)
+}
+
+fn sanitize(name: &str, profile: FsProfile, keep_extension: bool) -> String {
+    let mut cleaned: String = name
+        .chars()
+        .filter(|c| !is_invisible(*c))
+        .map(|c| {
+            if c.is_control() || profile.is_forbidden(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    // "." / ".." 以及以点开头的名称（隐藏文件）一律改为下划线开头
+    if cleaned.starts_with('.') {
+        cleaned.replace_range(..1, "_");
+    }
+
+    if profile.is_windows_like() {
+        cleaned = trim_windows_tail(&cleaned).to_string();
+        if is_windows_reserved(&cleaned) {
+            cleaned.insert(0, '_');
+        }
+    }
+
+    if cleaned.len() > MAX_NAME_BYTES {
+        cleaned = truncate_name(&cleaned, MAX_NAME_BYTES, keep_extension);
+        if profile.is_windows_like() {
+            cleaned = trim_windows_tail(&cleaned).to_string();
+        }
+    }
+
+    if cleaned.trim().is_empty() {
+        return "_".to_string();
+    }
+    cleaned
+}
+
+// 零宽字符、方向控制符、BOM 等不可见字符直接去掉
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{FEFF}'
+            | '\u{00AD}'
+    )
+}
+
+// Windows 会静默去掉结尾的点和空格
+fn trim_windows_tail(name: &str) -> &str {
+    name.trim_end_matches(['.', ' '])
+}
+
+fn is_windows_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    WINDOWS_RESERVED
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+// 按 UTF-8 字节截断，不拆分字符；文件名保留扩展名
+fn truncate_name(name: &str, max_bytes: usize, keep_extension: bool) -> String {
+    let ext = if keep_extension {
+        crate::layout::split_extension(name).1
+    } else {
+        None
+    };
+
+    match ext {
+        Some(ext) if ext.len() + 1 < max_bytes => {
+            let stem = &name[..name.len() - ext.len() - 1];
+            format!(
+                "{}.{}",
+                truncate_bytes(stem, max_bytes - ext.len() - 1),
+                ext
+            )
+        }
+        _ => truncate_bytes(name, max_bytes).to_string(),
+    }
+}
+
+pub fn truncate_bytes(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_forbidden_characters_per_profile() {
+        assert_eq!(
+            sanitize_file_name("a:b*c?.mp4", FsProfile::Windows),
+            "a_b_c_.mp4"
+        );
+        assert_eq!(
+            sanitize_file_name("a:b*c?.mp4", FsProfile::Macos),
+            "a_b*c?.mp4"
+        );
+        assert_eq!(sanitize_file_name("a:b/c", FsProfile::Posix), "a:b_c");
+        assert_eq!(
+            sanitize_file_name("a\u{0}b\u{200B}c", FsProfile::Posix),
+            "a_bc"
+        );
+    }
+
+    #[test]
+    fn rewrites_dot_names_and_windows_tail() {
+        assert_eq!(sanitize_dir_name("..", FsProfile::Posix), "_.");
+        assert_eq!(sanitize_dir_name(".hidden", FsProfile::Posix), "_hidden");
+        assert_eq!(sanitize_dir_name("name. . ", FsProfile::Windows), "name");
+        assert_eq!(sanitize_dir_name("name. ", FsProfile::Posix), "name. ");
+        assert_eq!(sanitize_file_name("   ", FsProfile::Windows), "_");
+        assert_eq!(sanitize_file_name("", FsProfile::Posix), "_");
+    }
+
+    #[test]
+    fn prefixes_windows_reserved_names() {
+        for name in [
+            "con",
+            "NUL.txt",
+            "COM0",
+            "lpt0.mp4",
+            "CONIN$",
+            "conout$.log",
+            "COM¹",
+        ] {
+            let cleaned = sanitize_file_name(name, FsProfile::Windows);
+            assert_eq!(cleaned, format!("_{}", name), "{}", name);
+        }
+        assert_eq!(
+            sanitize_file_name("CONSOLE.txt", FsProfile::Windows),
+            "CONSOLE.txt"
+        );
+        assert_eq!(sanitize_file_name("CON.txt", FsProfile::Posix), "CON.txt");
+    }
+
+    #[test]
+    fn truncates_to_max_bytes_keeping_extension() {
+        let long = format!("{}.mp4", "作".repeat(100));
+        let cleaned = sanitize_file_name(&long, FsProfile::Posix);
+        assert!(cleaned.len() <= MAX_NAME_BYTES);
+        assert!(cleaned.ends_with("作.mp4"));
+
+        // 目录名中的点不视为扩展名
+        let dir = format!("{}.mp4", "a".repeat(300));
+        assert_eq!(
+            sanitize_dir_name(&dir, FsProfile::Posix),
+            "a".repeat(MAX_NAME_BYTES)
+        );
+    }
+
+    #[test]
+    fn truncate_bytes_keeps_char_boundaries() {
+        assert_eq!(truncate_bytes("作品", 4), "作");
+        assert_eq!(truncate_bytes("作品", 6), "作品");
+        assert_eq!(truncate_bytes("ab", 0), "");
+    }
+
+    #[test]
+    fn normalizes_unicode_forms() {
+        let nfd = "e\u{301}";
+        assert_eq!(normalize(nfd, UnicodeForm::Nfc), "\u{e9}");
+        assert_eq!(normalize("\u{e9}", UnicodeForm::Nfd), nfd);
+        assert_eq!(normalize(nfd, UnicodeForm::None), nfd);
+        assert_eq!(comparison_key(nfd, UnicodeForm::None), "\u{e9}");
+    }
+}
@@ -0,0 +1,232 @@
+use crate::api::PosEduClient;
+use crate::credentials;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
+
+// 距离过期不足该秒数时提前刷新
+const REFRESH_MARGIN_SECS: i64 = 300;
+
+// 当前登录的令牌，由后端持有并负责刷新；刷新过程持锁，并发请求只会刷新一次
+static SESSION: once_cell::sync::Lazy<Mutex<Option<TokenSet>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TokenSet {
+    pub domain: String,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<i64>, // Unix 时间戳（秒），平台未返回 expires_in 时为空
+}
+
+impl TokenSet {
+    // 解析平台返回的令牌 JSON；刷新响应没有 refresh_token 时沿用 previous 中的
+    pub fn from_response(
+        domain: &str,
+        data: &serde_json::Value,
+        previous: Option<&TokenSet>,
+    ) -> Result<Self, AppError> {
+        let access_token = data
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| AppError::Decode("令牌响应缺少 access_token".to_string()))?;
+        let refresh_token = data
+            .get("refresh_token")
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .or_else(|| previous.and_then(|p| p.refresh_token.clone()));
+        let expires_in = data.get("expires_in").and_then(|v| {
+            v.as_i64()
+                .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+        });
+
+        Ok(Self {
+            domain: domain.to_string(),
+            access_token: access_token.to_string(),
+            refresh_token,
+            expires_at: expires_in.map(|secs| now() + secs),
+        })
+    }
+
+    fn expires_soon(&self) -> bool {
+        self.expires_at
+            .is_some_and(|at| at - now() <= REFRESH_MARGIN_SECS)
+    }
+}
+
+// 刷新后通知前端更新保存的令牌
+#[derive(Clone, Serialize, Debug)]
+pub struct SessionRefreshed {
+    pub domain: String,
+    pub access_token: String,
+    pub expires_at: Option<i64>,
+}
+
+// 需要重新登录
+#[derive(Clone, Serialize, Debug)]
+pub struct SessionExpired {
+    pub domain: String,
+    pub reason: String,
+}
+
+pub async fn set(tokens: TokenSet) {
+    *SESSION.lock().await = Some(tokens);
+}
+
+pub async fn current() -> Option<TokenSet> {
+    SESSION.lock().await.clone()
+}
+
+pub async fn clear() {
+    *SESSION.lock().await = None;
+}
+
+// 使用当前会话调用接口：即将过期时先刷新，返回 401 时刷新后重试一次；
+// 仍然失败则清除会话并发出 session://expired。
+// 后端没有该学校的会话时（如旧版本保存的令牌）使用前端传入的 fallback_token
+pub async fn call<T, F, Fut>(
+    app: &AppHandle,
+    domain: &str,
+    fallback_token: &str,
+    request: F,
+) -> Result<T, AppError>
+where
+    F: Fn(PosEduClient) -> Fut,
+    Fut: Future<Output = Result<T, AppError>>,
+{
+    let token = access_token(app, domain, fallback_token).await?;
+    match request(PosEduClient::new(domain).with_token(&token)).await {
+        Err(AppError::Unauthorized(reason)) => {
+            let token = match refresh(app, domain, &token).await {
+                Ok(Some(token)) => token,
+                Ok(None) => {
+                    expire(app, domain, &reason).await;
+                    return Err(AppError::Unauthorized(reason));
+                }
+                Err(e) => return Err(e),
+            };
+            let result = request(PosEduClient::new(domain).with_token(&token)).await;
+            if let Err(AppError::Unauthorized(ref reason)) = result {
+                expire(app, domain, reason).await;
+            }
+            result
+        }
+        result => result,
+    }
+}
+
+// 当前可用的访问令牌，即将过期时先刷新；刷新暂时失败时继续使用旧令牌
+async fn access_token(
+    app: &AppHandle,
+    domain: &str,
+    fallback_token: &str,
+) -> Result<String, AppError> {
+    let tokens = match current().await {
+        Some(tokens) if tokens.domain == domain => tokens,
+        _ => return Ok(fallback_token.to_string()),
+    };
+    if !tokens.expires_soon() || tokens.refresh_token.is_none() {
+        return Ok(tokens.access_token);
+    }
+
+    match refresh(app, domain, &tokens.access_token).await {
+        Ok(Some(token)) => Ok(token),
+        Ok(None) => {
+            expire(app, domain, "刷新令牌已失效").await;
+            Err(AppError::Unauthorized(String::new()))
+        }
+        Err(e) => {
+            eprintln!("⚠️ Token refresh failed, using current token: {}", e);
+            Ok(tokens.access_token)
+        }
+    }
+}
+
+// 用 refresh_token 刷新 stale_token；其他请求已经刷新过时直接返回新令牌。
+// 返回 Ok(None) 表示无法刷新（没有会话、没有 refresh_token 或被平台拒绝），需要重新登录
+async fn refresh(
+    app: &AppHandle,
+    domain: &str,
+    stale_token: &str,
+) -> Result<Option<String>, AppError> {
+    let mut session = SESSION.lock().await;
+    let Some(tokens) = session.as_ref().filter(|t| t.domain == domain) else {
+        return Ok(None);
+    };
+    if tokens.access_token != stale_token {
+        return Ok(Some(tokens.access_token.clone()));
+    }
+    let Some(ref refresh_token) = tokens.refresh_token else {
+        return Ok(None);
+    };
+
+    println!("🔑 Refreshing access token for {}", domain);
+    let data = match PosEduClient::new(domain).refresh_token(refresh_token).await {
+        Ok(data) => data,
+        // 网络问题不代表令牌失效，保留会话
+        Err(e) if e.retryable() => return Err(e),
+        Err(e) => {
+            eprintln!("❌ Token refresh rejected: {}", e);
+            return Ok(None);
+        }
+    };
+    let refreshed = TokenSet::from_response(domain, &data, Some(tokens))?;
+    let _ = app.emit(
+        "session://refreshed",
+        SessionRefreshed {
+            domain: domain.to_string(),
+            access_token: refreshed.access_token.clone(),
+            expires_at: refreshed.expires_at,
+        },
+    );
+    let token = refreshed.access_token.clone();
+    *session = Some(refreshed.clone());
+    drop(session);
+
+    // 已保存的登录信息同步更新，下次启动使用新令牌；写文件不占用会话锁
+    let saved = match app.path().app_data_dir() {
+        Ok(dir) => tokio::task::spawn_blocking(move || credentials::update(&dir, &refreshed))
+            .await
+            .unwrap_or_else(|e| Err(e.into())),
+        Err(e) => Err(AppError::from(e)),
+    };
+    if let Err(e) = saved {
+        eprintln!("⚠️ Failed to update saved credentials: {}", e);
+    }
+    Ok(Some(token))
+}
+
+async fn expire(app: &AppHandle, domain: &str, reason: &str) {
+    let matched = {
+        let mut session = SESSION.lock().await;
+        let matched = session.as_ref().is_some_and(|t| t.domain == domain);
+        if matched {
+            *session = None;
+        }
+        matched
+    };
+    // 保存的令牌已经无法使用；其他域名的 401 不影响当前会话保存的登录信息
+    if matched {
+        if let Ok(dir) = app.path().app_data_dir() {
+            if let Err(e) = credentials::delete(&dir) {
+                eprintln!("⚠️ Failed to delete saved credentials: {}", e);
+            }
+        }
+    }
+    eprintln!("🔒 Session expired for {}: {}", domain, reason);
+    let _ = app.emit(
+        "session://expired",
+        SessionExpired {
+            domain: domain.to_string(),
+            reason: reason.to_string(),
+        },
+    );
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
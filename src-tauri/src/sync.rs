@@ -0,0 +1,469 @@
+use crate::downloader::{DownloadItem, RemoteInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// 快照保存在同步根目录，随下载目录一起移动
+pub const SNAPSHOT_FILE: &str = ".match-sync.json";
+
+// 镜像模式移走的文件存放目录及日志
+pub const REMOVED_DIR: &str = "_removed";
+const REMOVED_LOG: &str = "removed.log";
+
+// 变化的文件重新下载期间，旧版本以该后缀暂存在原文件旁边
+const BACKUP_SUFFIX: &str = ".sync-old";
+
+// 上次同步成功的文件
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SnapshotEntry {
+    // 所属比赛和赛段，取自 SyncOptions；旧快照没有这两项
+    pub match_id: Option<i32>,
+    pub stage_id: Option<i32>,
+    pub work_id: i32,
+    pub file_id: i32,
+    pub createtime: i64,
+    pub url: String,
+    pub relative_path: String,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct Snapshot {
+    pub synced_at: String,
+    pub files: Vec<SnapshotEntry>,
+}
+
+impl Snapshot {
+    // 没有快照时视为首次同步
+    pub fn load(root: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(root.join(SNAPSHOT_FILE)) {
+            Ok(text) => serde_json::from_str(&text).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 先写临时文件再替换，中途退出不会留下损坏的快照
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(root)?;
+        let temp = root.join(format!("{}.tmp", SNAPSHOT_FILE));
+        let text = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(&temp, text)?;
+        std::fs::rename(&temp, root.join(SNAPSHOT_FILE))
+    }
+
+    // 合并本次同步范围的新记录：其他比赛/赛段的记录原样保留，
+    // 同一文件已有新记录时丢弃旧记录
+    fn merge_scope(&self, state: &SyncState, files: Vec<SnapshotEntry>) -> Self {
+        let keys: HashSet<(i32, i32)> = files.iter().map(|e| (e.work_id, e.file_id)).collect();
+        let mut merged: Vec<SnapshotEntry> = self
+            .files
+            .iter()
+            .filter(|e| !state.in_scope(e) && !keys.contains(&(e.work_id, e.file_id)))
+            .cloned()
+            .collect();
+        merged.extend(files);
+        merged.sort_by_key(|entry| (entry.work_id, entry.file_id));
+        Self {
+            synced_at: chrono::Local::now().to_rfc3339(),
+            files: merged,
+        }
+    }
+
+    pub fn index(&self) -> HashMap<(i32, i32), &SnapshotEntry> {
+        self.files
+            .iter()
+            .map(|entry| ((entry.work_id, entry.file_id), entry))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncStatus {
+    Added,
+    Changed,
+    Unchanged,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SyncOptions {
+    // 对已同步的文件发 HEAD 请求，比较 ETag / Last-Modified / 大小
+    pub check_validators: bool,
+    // 镜像模式：作品不再返回或被排除时，把本地文件移到 _removed/<日期>
+    pub mirror: bool,
+    // 这些审核状态的作品不下载，镜像模式下视为已移除
+    pub excluded_statuses: Vec<i32>,
+    // 本次同步的比赛和赛段。多个赛段同步到同一根目录时必须提供，
+    // 否则一个赛段的同步会把其他赛段的文件当作已移除
+    pub match_id: Option<i32>,
+    pub stage_id: Option<i32>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            check_validators: true,
+            mirror: false,
+            excluded_statuses: Vec::new(),
+            match_id: None,
+            stage_id: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct SyncFile {
+    pub work_id: i32,
+    pub file_id: i32,
+    pub student_id: i32,
+    pub name: String,
+    pub relative_path: String,
+    pub status: SyncStatus,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct SyncReport {
+    pub batch_id: String,
+    pub save_path: String,
+    pub added: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+    pub files: Vec<SyncFile>,
+    pub removed: Vec<SnapshotEntry>, // 快照中有、本次作品列表中已没有的文件
+    pub moved: Vec<MovedFile>,       // 镜像模式下移走的文件
+}
+
+// 镜像模式移走的文件，路径相对于同步根目录
+#[derive(Clone, Serialize, Debug)]
+pub struct MovedFile {
+    pub work_id: i32,
+    pub file_id: i32,
+    pub from: String,
+    pub to: Option<String>, // None 且无错误表示本地文件早已不存在
+    pub reason: String,
+    pub error: Option<String>,
+}
+
+// 同步批次进行中的状态：未变化的文件沿用旧记录，下载成功的文件在批次结束时写入
+#[derive(Clone, Debug, Default)]
+pub struct SyncState {
+    pub match_id: Option<i32>,
+    pub stage_id: Option<i32>,
+    pub unchanged: Vec<SnapshotEntry>,
+    pub pending: HashMap<String, SnapshotEntry>, // 下载项 id -> 新记录
+    pub replaced: HashMap<String, Replaced>,     // 下载项 id -> 暂存的旧版本
+}
+
+// 重新下载中的变化文件：新版本下载成功前旧版本一直保留
+#[derive(Clone, Debug)]
+pub struct Replaced {
+    pub previous: SnapshotEntry,
+    pub path: PathBuf,
+    pub backup: PathBuf,
+}
+
+impl SyncState {
+    // 只有同一比赛、同一赛段的记录才参与本次比较和移除
+    pub fn in_scope(&self, entry: &SnapshotEntry) -> bool {
+        entry.match_id == self.match_id && entry.stage_id == self.stage_id
+    }
+
+    // 合并下载成功的文件，生成新快照；会访问磁盘，需在阻塞线程中调用。
+    // 重新下载成功的文件删除暂存的旧版本，失败的恢复旧版本并保留旧记录，
+    // 无法处理的暂存文件作为警告返回。previous 为写入前重新读取的快照，
+    // 期间其他赛段的同步写入的记录不会被覆盖
+    pub fn finish(
+        &self,
+        previous: &Snapshot,
+        completed: &HashMap<String, (Option<u64>, Option<String>)>,
+    ) -> (Snapshot, Vec<String>) {
+        let mut files = self.unchanged.clone();
+        let mut warnings = Vec::new();
+        for (id, entry) in &self.pending {
+            if let Some((size, sha256)) = completed.get(id) {
+                files.push(SnapshotEntry {
+                    size: *size,
+                    sha256: sha256.clone(),
+                    ..entry.clone()
+                });
+            }
+        }
+        for (id, replaced) in &self.replaced {
+            if completed.contains_key(id) {
+                if let Err(e) = std::fs::remove_file(&replaced.backup) {
+                    warnings.push(format!(
+                        "Failed to remove {}: {}",
+                        replaced.backup.display(),
+                        e
+                    ));
+                }
+                continue;
+            }
+            match restore_backup(&replaced.path, &replaced.backup) {
+                Ok(()) => files.push(replaced.previous.clone()),
+                Err(e) => warnings.push(format!(
+                    "Failed to restore {}: {}",
+                    replaced.path.display(),
+                    e
+                )),
+            }
+        }
+        (previous.merge_scope(self, files), warnings)
+    }
+}
+
+// 重新下载前把旧版本移到 <文件名>.sync-old，返回暂存位置（没有旧版本时为 None）。
+// 上次同步中断留下的暂存文件才是最后的完整版本，此时保留它并丢弃未下载完的文件
+pub fn set_aside(path: &Path) -> io::Result<Option<PathBuf>> {
+    let backup = backup_path(path);
+    if backup.exists() {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        return Ok(Some(backup));
+    }
+    if !path.exists() {
+        return Ok(None);
+    }
+    std::fs::rename(path, &backup)?;
+    Ok(Some(backup))
+}
+
+fn restore_backup(path: &Path, backup: &Path) -> io::Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    std::fs::rename(backup, path)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(BACKUP_SUFFIX);
+    path.with_file_name(name)
+}
+
+// 判断单个文件相对上次同步的状态
+pub fn classify(
+    item: &DownloadItem,
+    previous: Option<&SnapshotEntry>,
+    remote: Option<&RemoteInfo>,
+) -> (SyncStatus, Option<String>) {
+    let Some(previous) = previous else {
+        return (SyncStatus::Added, None);
+    };
+    let meta = item.meta.clone().unwrap_or_default();
+
+    if previous.createtime != meta.createtime {
+        return changed("作品提交时间已变化");
+    }
+    if previous.url != item.url {
+        return changed("下载地址已变化");
+    }
+
+    let local = Path::new(&item.save_path).join(&item.filename);
+    match (std::fs::metadata(&local), previous.size) {
+        (Err(_), _) => return changed("本地文件不存在"),
+        (Ok(metadata), Some(size)) if metadata.len() != size => {
+            return changed("本地文件大小与上次同步不一致")
+        }
+        _ => {}
+    }
+
+    if let Some(remote) = remote {
+        if differs(&previous.etag, &remote.etag) {
+            return changed("ETag 已变化");
+        }
+        if differs(&previous.last_modified, &remote.last_modified) {
+            return changed("Last-Modified 已变化");
+        }
+        if let (Some(old), Some(new)) = (previous.size, remote.size) {
+            if old != new {
+                return changed("远程文件大小已变化");
+            }
+        }
+    }
+
+    (SyncStatus::Unchanged, None)
+}
+
+fn changed(reason: &str) -> (SyncStatus, Option<String>) {
+    (SyncStatus::Changed, Some(reason.to_string()))
+}
+
+// 两边都有值且不同才算变化，缺少校验信息时不判定
+fn differs(old: &Option<String>, new: &Option<String>) -> bool {
+    matches!((old, new), (Some(old), Some(new)) if old != new)
+}
+
+// 把已移除作品的文件移到 _removed/<日期>/ 下并保留原有相对路径，不做任何删除；
+// 移走后留下的空目录（如学生文件夹）一并清理，每次移动写入 _removed/removed.log
+pub fn move_to_removed(root: &Path, entries: &[(SnapshotEntry, String)]) -> Vec<MovedFile> {
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let target_root = root.join(REMOVED_DIR).join(&date);
+    let mut moved = Vec::new();
+
+    for (entry, reason) in entries {
+        let mut record = MovedFile {
+            work_id: entry.work_id,
+            file_id: entry.file_id,
+            from: entry.relative_path.clone(),
+            to: None,
+            reason: reason.clone(),
+            error: None,
+        };
+
+        // 快照可被手工修改，路径必须仍在同步根目录内
+        let segments: Vec<&str> = entry.relative_path.split('/').collect();
+        let source = match crate::paths::resolve_within(root, &segments) {
+            Ok(source) => source,
+            Err(e) => {
+                record.error = Some(e.to_string());
+                moved.push(record);
+                continue;
+            }
+        };
+
+        // 本地已经没有的文件无需移动，只记录日志
+        if !source.exists() {
+            moved.push(record);
+            continue;
+        }
+
+        let target = unique_target(&target_root.join(&entry.relative_path));
+        let result = target
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::rename(&source, &target));
+        match result {
+            Ok(()) => {
+                record.to = Some(
+                    Path::new(REMOVED_DIR)
+                        .join(&date)
+                        .join(target.strip_prefix(&target_root).unwrap_or(&target))
+                        .to_string_lossy()
+                        .replace('\\', "/"),
+                );
+                prune_empty_dirs(root, source.parent());
+            }
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        moved.push(record);
+    }
+
+    if let Err(e) = append_removed_log(root, &moved) {
+        eprintln!("⚠️ Failed to write {}: {}", REMOVED_LOG, e);
+    }
+    moved
+}
+
+// 同一天多次同步移走同名文件时追加序号
+fn unique_target(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (stem, ext) = crate::layout::split_extension(&name);
+    (2..)
+        .map(|n| match ext {
+            Some(ext) => path.with_file_name(format!("{} ({}).{}", stem, n, ext)),
+            None => path.with_file_name(format!("{} ({})", stem, n)),
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+// 自下而上删除空目录，直到 root（不含）
+fn prune_empty_dirs(root: &Path, mut dir: Option<&Path>) {
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) {
+            break;
+        }
+        // remove_dir 只会删除空目录
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+fn append_removed_log(root: &Path, moved: &[MovedFile]) -> io::Result<()> {
+    if moved.is_empty() {
+        return Ok(());
+    }
+    let dir = root.join(REMOVED_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let mut log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(REMOVED_LOG))?;
+
+    let now = chrono::Local::now().to_rfc3339();
+    for record in moved {
+        let result = match (&record.to, &record.error) {
+            (Some(to), _) => format!("-> {}", to),
+            (None, Some(error)) => format!("FAILED: {}", error),
+            (None, None) => "(already missing)".to_string(),
+        };
+        writeln!(
+            log,
+            "{}\twork={}\tfile={}\t{}\t{}\t{}",
+            now, record.work_id, record.file_id, record.from, result, record.reason
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(stage_id: Option<i32>, file_id: i32) -> SnapshotEntry {
+        SnapshotEntry {
+            match_id: Some(1),
+            stage_id,
+            work_id: 10,
+            file_id,
+            createtime: 0,
+            url: String::new(),
+            relative_path: format!("{}.mp4", file_id),
+            size: None,
+            sha256: None,
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    #[test]
+    fn finish_keeps_entries_of_other_stages() {
+        let previous = Snapshot {
+            synced_at: String::new(),
+            files: vec![entry(Some(1), 1), entry(Some(2), 2), entry(None, 3)],
+        };
+        let state = SyncState {
+            match_id: Some(1),
+            stage_id: Some(2),
+            unchanged: vec![entry(Some(2), 3)],
+            ..Default::default()
+        };
+
+        let (snapshot, warnings) = state.finish(&previous, &HashMap::new());
+        assert!(warnings.is_empty());
+        let kept: Vec<(Option<i32>, i32)> = snapshot
+            .files
+            .iter()
+            .map(|e| (e.stage_id, e.file_id))
+            .collect();
+        // 赛段 2 的旧记录被本次结果替换，赛段 1 的记录保留，无范围的旧记录被同一文件的新记录取代
+        assert_eq!(kept, vec![(Some(1), 1), (Some(2), 3)]);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::commands::Work;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// 工作表名最长 31 个字符
+const MAX_SHEET_NAME_CHARS: usize = 31;
+
+#[derive(Clone, Serialize, Debug, Default)]
+pub struct XlsxSummary {
+    pub path: String,
+    pub works: usize,
+    pub files: usize,
+    pub linked_files: usize,
+    pub colleges: usize,
+}
+
+// 已下载到本地的文件：(作品ID, 文件ID) -> 本地路径
+pub type LocalFiles = HashMap<(i32, i32), PathBuf>;
+
+// 生成评审用工作簿：作品表、文件表、学院汇总表和每个学院的明细表
+pub fn write_works_workbook(
+    dest: &Path,
+    works: &[Work],
+    local_files: &LocalFiles,
+) -> Result<XlsxSummary, XlsxError> {
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold().set_background_color("#DDEBF7");
+
+    let mut summary = XlsxSummary {
+        path: dest.to_string_lossy().into_owned(),
+        works: works.len(),
+        ..Default::default()
+    };
+
+    // 作品表
+    {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("作品")?;
+        let columns = [
+            ("作品ID", 10),
+            ("作品标题", 30),
+            ("学号", 14),
+            ("姓名", 12),
+            ("学院", 20),
+            ("专业", 20),
+            ("班级", 16),
+            ("比赛", 24),
+            ("赛段", 16),
+            ("审核状态", 10),
+            ("提交时间", 20),
+            ("文件数", 8),
+            ("已下载", 8),
+            ("本地文件夹", 40),
+        ];
+        write_header(sheet, &columns, &header)?;
+
+        for (i, work) in works.iter().enumerate() {
+            let row = i as u32 + 1;
+            let downloaded = downloaded_count(work, local_files);
+            sheet.write_number(row, 0, work.id)?;
+            sheet.write_string(row, 1, &work.title)?;
+            sheet.write_number(row, 2, work.student_id)?;
+            sheet.write_string(row, 3, text(&work.student_name))?;
+            sheet.write_string(row, 4, text(&work.college_name))?;
+            sheet.write_string(row, 5, text(&work.major_name))?;
+            sheet.write_string(row, 6, text(&work.class_name))?;
+            sheet.write_string(row, 7, text(&work.match_title))?;
+            sheet.write_string(row, 8, text(&work.stage_name))?;
+            sheet.write_number(row, 9, work.check_status)?;
+            sheet.write_string(row, 10, format_time(work.createtime))?;
+            sheet.write_number(row, 11, work.files.len() as u32)?;
+            sheet.write_number(row, 12, downloaded as u32)?;
+            if let Some(url) = work_folder(work, local_files).and_then(|f| file_url(&f)) {
+                sheet.write_url_with_text(row, 13, url.as_str(), "打开文件夹")?;
+            }
+        }
+        finish_table(sheet, works.len(), columns.len())?;
+    }
+
+    // 文件表
+    {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("文件")?;
+        let columns = [
+            ("作品ID", 10),
+            ("作品标题", 30),
+            ("学号", 14),
+            ("姓名", 12),
+            ("学院", 20),
+            ("文件ID", 10),
+            ("元素", 16),
+            ("元素类型", 10),
+            ("原始文件名", 36),
+            ("下载地址", 40),
+            ("本地文件", 40),
+        ];
+        write_header(sheet, &columns, &header)?;
+
+        let mut row = 0u32;
+        for work in works {
+            for file in &work.files {
+                row += 1;
+                sheet.write_number(row, 0, work.id)?;
+                sheet.write_string(row, 1, &work.title)?;
+                sheet.write_number(row, 2, work.student_id)?;
+                sheet.write_string(row, 3, text(&work.student_name))?;
+                sheet.write_string(row, 4, text(&work.college_name))?;
+                sheet.write_number(row, 5, file.id)?;
+                sheet.write_string(row, 6, &file.element_label)?;
+                sheet.write_number(row, 7, file.element_type)?;
+                sheet.write_string(row, 8, &file.user_content.name)?;
+                sheet.write_string(row, 9, &file.user_content.url)?;
+                let local = local_files.get(&(work.id, file.id));
+                if let Some((path, url)) = local.and_then(|p| file_url(p).map(|url| (p, url))) {
+                    let name = path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    sheet.write_url_with_text(row, 10, url.as_str(), name)?;
+                    summary.linked_files += 1;
+                }
+            }
+        }
+        summary.files = row as usize;
+        finish_table(sheet, row as usize, columns.len())?;
+    }
+
+    // 按学院分组（BTreeMap 保证表顺序稳定）
+    let mut colleges: BTreeMap<String, Vec<&Work>> = BTreeMap::new();
+    for work in works {
+        colleges
+            .entry(
+                work.college_name
+                    .as_deref()
+                    .unwrap_or("未知学院")
+                    .to_string(),
+            )
+            .or_default()
+            .push(work);
+    }
+    summary.colleges = colleges.len();
+
+    // 学院汇总表
+    {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("学院汇总")?;
+        let columns = [
+            ("学院", 24),
+            ("作品数", 10),
+            ("学生数", 10),
+            ("文件数", 10),
+            ("已下载文件", 12),
+        ];
+        write_header(sheet, &columns, &header)?;
+
+        for (i, (college, works)) in colleges.iter().enumerate() {
+            let row = i as u32 + 1;
+            let students: HashSet<i32> = works.iter().map(|w| w.student_id).collect();
+            let files: usize = works.iter().map(|w| w.files.len()).sum();
+            let downloaded: usize = works.iter().map(|w| downloaded_count(w, local_files)).sum();
+            sheet.write_string(row, 0, college)?;
+            sheet.write_number(row, 1, works.len() as u32)?;
+            sheet.write_number(row, 2, students.len() as u32)?;
+            sheet.write_number(row, 3, files as u32)?;
+            sheet.write_number(row, 4, downloaded as u32)?;
+        }
+        finish_table(sheet, colleges.len(), columns.len())?;
+    }
+
+    // 每个学院一张明细表
+    let mut used_names: HashSet<String> = ["作品", "文件", "学院汇总"]
+        .iter()
+        .map(|s| s.to_lowercase())
+        .collect();
+    for (college, works) in &colleges {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(unique_sheet_name(college, &mut used_names))?;
+        let columns = [
+            ("专业", 20),
+            ("班级", 16),
+            ("学号", 14),
+            ("姓名", 12),
+            ("作品标题", 30),
+            ("审核状态", 10),
+            ("文件数", 8),
+            ("已下载", 8),
+            ("本地文件夹", 40),
+        ];
+        write_header(sheet, &columns, &header)?;
+
+        let mut sorted = works.clone();
+        sorted.sort_by(|a, b| {
+            (text(&a.major_name), text(&a.class_name), a.student_id).cmp(&(
+                text(&b.major_name),
+                text(&b.class_name),
+                b.student_id,
+            ))
+        });
+
+        for (i, work) in sorted.iter().enumerate() {
+            let row = i as u32 + 1;
+            sheet.write_string(row, 0, text(&work.major_name))?;
+            sheet.write_string(row, 1, text(&work.class_name))?;
+            sheet.write_number(row, 2, work.student_id)?;
+            sheet.write_string(row, 3, text(&work.student_name))?;
+            sheet.write_string(row, 4, &work.title)?;
+            sheet.write_number(row, 5, work.check_status)?;
+            sheet.write_number(row, 6, work.files.len() as u32)?;
+            sheet.write_number(row, 7, downloaded_count(work, local_files) as u32)?;
+            if let Some(url) = work_folder(work, local_files).and_then(|f| file_url(&f)) {
+                sheet.write_url_with_text(row, 8, url.as_str(), "打开文件夹")?;
+            }
+        }
+        finish_table(sheet, sorted.len(), columns.len())?;
+    }
+
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    workbook.save(dest)?;
+
+    Ok(summary)
+}
+
+fn write_header(
+    sheet: &mut Worksheet,
+    columns: &[(&str, u16)],
+    format: &Format,
+) -> Result<(), XlsxError> {
+    for (col, (title, width)) in columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *title, format)?;
+        sheet.set_column_width(col as u16, *width)?;
+    }
+    Ok(())
+}
+
+// 冻结表头并加自动筛选，方便评委在 Excel 里排序
+fn finish_table(sheet: &mut Worksheet, rows: usize, columns: usize) -> Result<(), XlsxError> {
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofilter(0, 0, rows as u32, columns as u16 - 1)?;
+    Ok(())
+}
+
+fn text(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("")
+}
+
+fn format_time(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+fn downloaded_count(work: &Work, local_files: &LocalFiles) -> usize {
+    work.files
+        .iter()
+        .filter(|f| local_files.contains_key(&(work.id, f.id)))
+        .count()
+}
+
+fn work_folder(work: &Work, local_files: &LocalFiles) -> Option<PathBuf> {
+    work.files
+        .iter()
+        .find_map(|f| local_files.get(&(work.id, f.id)))
+        .and_then(|p| p.parent())
+        .map(Path::to_path_buf)
+}
+
+// 本地路径转 file:// 链接，# % 空格等字符按 URL 规则编码，Windows 路径转为 file:///C:/…
+fn file_url(path: &Path) -> Option<String> {
+    let path = std::path::absolute(path).ok()?;
+    url::Url::from_file_path(path).ok().map(String::from)
+}
+
+// 工作表名不能含 []:*?/\，最长 31 个字符，且不能重复（Excel 不区分大小写，used 中存小写）
+fn unique_sheet_name(name: &str, used: &mut HashSet<String>) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            _ => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim_matches('\'');
+    let base = if cleaned.is_empty() {
+        "未知学院"
+    } else {
+        cleaned
+    };
+
+    let mut candidate: String = base.chars().take(MAX_SHEET_NAME_CHARS).collect();
+    let mut n = 2;
+    while !used.insert(candidate.to_lowercase()) {
+        let suffix = format!("_{}", n);
+        let keep = MAX_SHEET_NAME_CHARS - suffix.chars().count();
+        candidate = format!("{}{}", base.chars().take(keep).collect::<String>(), suffix);
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sheet_names_are_cleaned_and_truncated() {
+        let mut used = HashSet::new();
+        assert_eq!(unique_sheet_name("'a/b:c'", &mut used), "a_b_c");
+        assert_eq!(unique_sheet_name("''", &mut used), "未知学院");
+
+        let long = "计".repeat(40);
+        let name = unique_sheet_name(&long, &mut used);
+        assert_eq!(name.chars().count(), MAX_SHEET_NAME_CHARS);
+        let name = unique_sheet_name(&long, &mut used);
+        assert_eq!(name.chars().count(), MAX_SHEET_NAME_CHARS);
+        assert!(name.ends_with("_2"));
+    }
+
+    #[test]
+    fn sheet_names_dedupe_case_insensitively() {
+        let mut used = HashSet::new();
+        assert_eq!(unique_sheet_name("Arts", &mut used), "Arts");
+        assert_eq!(unique_sheet_name("ARTS", &mut used), "ARTS_2");
+        assert_eq!(unique_sheet_name("arts", &mut used), "arts_3");
+        assert_eq!(unique_sheet_name("Arts_2", &mut used), "Arts_2_2");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_url_encodes_special_characters() {
+        let url = file_url(Path::new("/tmp/作品 #1/50%.pdf")).unwrap();
+        assert_eq!(url, "file:///tmp/%E4%BD%9C%E5%93%81%20%231/50%25.pdf");
+    }
+}
@@ -0,0 +1,6 @@
+// 后端命令的错误为 {code, message, status, retryable}，其他来源的错误可能是字符串
+export function errorText(error) {
+  if (typeof error === 'string') return error;
+  if (error && error.message) return error.message;
+  return JSON.stringify(error);
+}